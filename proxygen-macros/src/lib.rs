@@ -1,6 +1,6 @@
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{FnArg, ItemFn};
+use syn::{Attribute, FnArg, ItemFn};
 
 const GET_ARG_TYPES: fn(&FnArg) -> syn::Ident = |arg: &FnArg| match arg {
     FnArg::Receiver(_) => panic!("Cannot use receivers (self) with proxy functions"),
@@ -28,6 +28,87 @@ const GET_ARG_NAMES: fn(&FnArg) -> syn::Ident = |arg: &FnArg| match arg {
     }
 };
 
+/// Strips the decoration LLVM adds itself to stdcall (`_Foo@12`) and fastcall (`@Foo@8`) symbols on x86
+fn undecorate(export_name: &str, abi: &str) -> Option<String> {
+    let (name, arg_bytes) = export_name.rsplit_once('@')?;
+    if arg_bytes.is_empty() || !arg_bytes.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    match abi {
+        "stdcall" | "system" => name.strip_prefix('_'),
+        "fastcall" => name.strip_prefix('@'),
+        _ => None,
+    }
+    .map(String::from)
+}
+
+/// Keeps a decorated `#[export_name]` from being decorated twice on i686 MSVC.
+///
+/// LLVM decorates stdcall and fastcall symbols itself, so `#[export_name = "_Foo@12"]` on an `extern "stdcall"` function
+/// ends up as `__Foo@12@12`. On that target, the function is given the undecorated name instead, which LLVM decorates
+/// back into `_Foo@12`, and `module.def` exports it under the original name.
+fn undecorate_export_name(attrs: Vec<Attribute>, abi: Option<&syn::Abi>) -> Vec<Attribute> {
+    let abi = match abi.and_then(|abi| abi.name.as_ref()) {
+        Some(abi) => abi.value(),
+        None => return attrs,
+    };
+    attrs
+        .into_iter()
+        .flat_map(|attr| {
+            if let syn::Meta::NameValue(syn::MetaNameValue {
+                path,
+                value:
+                    syn::Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Str(export_name),
+                        ..
+                    }),
+                ..
+            }) = &attr.meta
+            {
+                if path.is_ident("export_name") {
+                    if let Some(undecorated) = undecorate(&export_name.value(), &abi) {
+                        return vec![
+                            syn::parse_quote!(#[cfg_attr(all(target_arch = "x86", target_env = "msvc"), export_name = #undecorated)]),
+                            syn::parse_quote!(#[cfg_attr(not(all(target_arch = "x86", target_env = "msvc")), export_name = #export_name)]),
+                        ];
+                    }
+                }
+            }
+            vec![attr]
+        })
+        .collect()
+}
+
+/// The name of the export a hook intercepts: its `#[export_name]`, or the function's own name if it has none
+fn hooked_export_name(attrs: &[Attribute], func_name: &syn::Ident) -> String {
+    attrs
+        .iter()
+        .find_map(|attr| match &attr.meta {
+            syn::Meta::NameValue(syn::MetaNameValue {
+                path,
+                value:
+                    syn::Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Str(export_name),
+                        ..
+                    }),
+                ..
+            }) if path.is_ident("export_name") => Some(export_name.value()),
+            _ => None,
+        })
+        .unwrap_or_else(|| func_name.to_string())
+}
+
+/// The hooked export's index into `ORIGINAL_FUNCS`, looked up by its name at compile time.
+///
+/// Hooks can be named however you like, since only their `#[export_name]` has to match the original export.
+fn orig_index(attrs: &[Attribute], func_name: &syn::Ident) -> syn::Expr {
+    let export_name = hooked_export_name(attrs, func_name);
+    syn::parse_quote!({
+        const INDEX: usize = crate::export_indices::index_of(#export_name);
+        INDEX
+    })
+}
+
 #[derive(Debug, Clone, Copy)]
 enum ProxySignatureType {
     Known,
@@ -41,7 +122,7 @@ impl From<syn::Meta> for ProxySignatureType {
             syn::Meta::List(_) => panic!("Unsupported attribute inputs"),
             syn::Meta::NameValue(sig) => {
                 if let Some(ident) = sig.path.get_ident() {
-                    if ident.to_string() != "sig" {
+                    if *ident != "sig" {
                         panic!("Expected sig=\"unknown\" or sig=\"known\"")
                     }
                     if let syn::Expr::Lit(syn::ExprLit {
@@ -74,14 +155,13 @@ pub fn forward(_attr_input: TokenStream, item: TokenStream) -> TokenStream {
     let func_name = input.sig.clone().ident;
     let func_body = input.block.stmts.clone();
     let ret_type = input.sig.output.clone();
-    let orig_index_ident =
-        syn::parse_str::<syn::Path>(&format!("crate::export_indices::Index_{}", &func_name))
-            .unwrap();
+    let orig_index_ident = orig_index(&input.attrs, &func_name);
     let arg_types = input.sig.inputs.iter().map(GET_ARG_TYPES);
     let attrs = input
         .attrs
         .into_iter()
-        .filter(|attr| !attr.path().is_ident("proxy"));
+        .filter(|attr| !attr.path().is_ident("proxy"))
+        .collect::<Vec<_>>();
 
     if arg_types.len() > 0 {
         panic!("You may not specifiy arguments in a forwarding proxy");
@@ -102,45 +182,44 @@ pub fn forward(_attr_input: TokenStream, item: TokenStream) -> TokenStream {
             _ => panic!("You may not specify a return type when forwarding a function call"),
         },
     };
-    if func_body.len() > 0 {
+    if !func_body.is_empty() {
         panic!("Your function body will not get run in a forwarding proxy. Perhaps you meant to use a `pre_hook`?");
     }
 
+    // A naked function's body has to be nothing but its assembly, so each arch gets its own copy of the thunk
     TokenStream::from(quote!(
-        #[naked]
+        #[cfg(target_arch = "x86_64")]
+        #[unsafe(naked)]
         #(#attrs)*
         pub unsafe extern "C" fn #func_name() {
-            #[cfg(target_arch = "x86_64")]
-            {
-                std::arch::asm!(
-                    "call {wait_dll_proxy_init}",
-                    "mov rax, qword ptr [rip + {ORIG_FUNCS_PTR}]",
-                    "add rax, {orig_index} * 8",
-                    "mov rax, qword ptr [rax]",
-                    "push rax",
-                    "ret",
-                    wait_dll_proxy_init = sym crate::wait_dll_proxy_init,
-                    ORIG_FUNCS_PTR = sym crate::ORIG_FUNCS_PTR,
-                    orig_index = const #orig_index_ident,
-                    options(noreturn)
-                )
-            }
+            std::arch::naked_asm!(
+                "call {wait_dll_proxy_init}",
+                "mov rax, qword ptr [rip + {ORIG_FUNCS_PTR}]",
+                "add rax, {orig_index} * 8",
+                "mov rax, qword ptr [rax]",
+                "push rax",
+                "ret",
+                wait_dll_proxy_init = sym crate::wait_dll_proxy_init,
+                ORIG_FUNCS_PTR = sym crate::ORIG_FUNCS_PTR,
+                orig_index = const #orig_index_ident,
+            )
+        }
 
-            #[cfg(target_arch = "x86")]
-            {
-                std::arch::asm!(
-                    "call {wait_dll_proxy_init}",
-                    "mov eax, dword ptr [{ORIG_FUNCS_PTR}]",
-                    "add eax, {orig_index} * 4",
-                    "mov eax, dword ptr [eax]",
-                    "push eax",
-                    "ret",
-                    wait_dll_proxy_init = sym crate::wait_dll_proxy_init,
-                    ORIG_FUNCS_PTR = sym crate::ORIG_FUNCS_PTR,
-                    orig_index = const #orig_index_ident,
-                    options(noreturn)
-                )
-            }
+        #[cfg(target_arch = "x86")]
+        #[unsafe(naked)]
+        #(#attrs)*
+        pub unsafe extern "C" fn #func_name() {
+            std::arch::naked_asm!(
+                "call {wait_dll_proxy_init}",
+                "mov eax, dword ptr [{ORIG_FUNCS_PTR}]",
+                "add eax, {orig_index} * 4",
+                "mov eax, dword ptr [eax]",
+                "push eax",
+                "ret",
+                wait_dll_proxy_init = sym crate::wait_dll_proxy_init,
+                ORIG_FUNCS_PTR = sym crate::ORIG_FUNCS_PTR,
+                orig_index = const #orig_index_ident,
+            )
         }
     ))
 }
@@ -154,14 +233,14 @@ pub fn proxy(attr_input: TokenStream, item: TokenStream) -> TokenStream {
     let func_sig = input.sig.clone();
     let func_body = input.block.stmts.clone();
     let ret_type = input.sig.output.clone();
-    let orig_index_ident =
-        syn::parse_str::<syn::Path>(&format!("crate::export_indices::Index_{}", &func_name))
-            .unwrap();
+    let orig_index_ident = orig_index(&input.attrs, &func_name);
     let arg_types = input.sig.inputs.iter().map(GET_ARG_TYPES);
     let attrs = input
         .attrs
         .into_iter()
-        .filter(|attr| !attr.path().is_ident("proxy"));
+        .filter(|attr| !attr.path().is_ident("proxy"))
+        .collect::<Vec<_>>();
+    let attrs = undecorate_export_name(attrs, input.sig.abi.as_ref());
     let sig_type: ProxySignatureType = match attr_input {
         Ok(attr_input) => attr_input.into(),
         Err(_) => panic!("Please explictly set sig=\"known\" or sig=\"unknown\". Eg. #[post_hook(sig = \"known\")]"),
@@ -197,29 +276,32 @@ pub fn pre_hook(attr_input: TokenStream, item: TokenStream) -> TokenStream {
     let func_sig = input.sig.clone();
     let func_body = input.block.stmts.clone();
     let ret_type = input.sig.output.clone();
-    let orig_index_ident =
-        syn::parse_str::<syn::Path>(&format!("crate::export_indices::Index_{}", &func_name))
-            .unwrap();
+    let orig_index_ident = orig_index(&input.attrs, &func_name);
     let arg_names = input.sig.inputs.iter().map(GET_ARG_NAMES);
     let arg_types = input.sig.inputs.iter().map(GET_ARG_TYPES);
     let attrs = input
         .attrs
         .into_iter()
-        .filter(|attr| !attr.path().is_ident("pre_hook"));
+        .filter(|attr| !attr.path().is_ident("pre_hook"))
+        .collect::<Vec<_>>();
     let sig_type: ProxySignatureType = match attr_input {
             Ok(attr_input) => attr_input.into(),
             Err(_) => panic!("Please explictly set sig=\"known\" or sig=\"unknown\". Eg. #[post_hook(sig = \"known\")]"),
         };
 
     match sig_type {
-        ProxySignatureType::Known => TokenStream::from(quote!(
-            #(#attrs)*
-            #func_sig {
-                let orig_func: fn (#(#arg_types,)*) #ret_type = unsafe { std::mem::transmute(crate::ORIGINAL_FUNCS[#orig_index_ident]) };
-                #(#func_body)*
-                orig_func(#(#arg_names,)*)
-            }
-        )),
+        ProxySignatureType::Known => {
+            // Only known signatures keep the ABI they were written with. Unknown ones get an `extern "C"` thunk
+            let attrs = undecorate_export_name(attrs, input.sig.abi.as_ref());
+            TokenStream::from(quote!(
+                #(#attrs)*
+                #func_sig {
+                    let orig_func: fn (#(#arg_types,)*) #ret_type = unsafe { std::mem::transmute(crate::ORIGINAL_FUNCS[#orig_index_ident]) };
+                    #(#func_body)*
+                    orig_func(#(#arg_names,)*)
+                }
+            ))
+        }
         ProxySignatureType::Unknown => {
             if arg_names.clone().len() != 0 {
                 panic!("You may not specifiy any arguments when proxying a function with an unknown signature");
@@ -244,17 +326,19 @@ pub fn pre_hook(attr_input: TokenStream, item: TokenStream) -> TokenStream {
                 #[cfg(not(target_arch = "x86_64"))]
                 compile_error!("Pre-hooks aren't yet implemented for non x86-64");
 
-                #[no_mangle]
+                // The hook is only called from the thunk below, so it keeps a private mangled symbol. That can't
+                // collide with the DLL's exports, or with a hook of the same name in another module
                 // TODO: Use the same safety/unsafety modifier as the original here
-                pub unsafe extern "C" fn #hook_func_name() {
+                unsafe extern "C" fn #hook_func_name() {
                     let orig_func: fn () = std::mem::transmute(crate::ORIGINAL_FUNCS[#orig_index_ident]);
                     #(#func_body)*
                 }
 
-                #[naked]
+                #[cfg(target_arch = "x86_64")]
+                #[unsafe(naked)]
                 #(#attrs)*
                 pub unsafe extern "C" fn #func_name() {
-                    std::arch::asm!(
+                    std::arch::naked_asm!(
                         // Wait for dll proxy to initialize
                         "call {wait_dll_proxy_init}",
                         "mov rax, qword ptr [rip + {ORIG_FUNCS_PTR}]",
@@ -293,8 +377,7 @@ pub fn pre_hook(attr_input: TokenStream, item: TokenStream) -> TokenStream {
                         ORIG_FUNCS_PTR = sym crate::ORIG_FUNCS_PTR,
                         orig_index = const #orig_index_ident,
                         proxygen_pre_hook_func = sym #hook_func_name,
-                        options(noreturn)
-                    );
+                    )
                 }
             ))
         }
@@ -314,15 +397,15 @@ pub fn post_hook(attr_input: TokenStream, item: TokenStream) -> TokenStream {
     let func_sig = input.sig.clone();
     let func_body = input.block.stmts.clone();
     let ret_type = input.sig.output.clone();
-    let orig_index_ident =
-        syn::parse_str::<syn::Path>(&format!("crate::export_indices::Index_{}", &func_name))
-            .unwrap();
+    let orig_index_ident = orig_index(&input.attrs, &func_name);
     let arg_names = input.sig.inputs.iter().map(GET_ARG_NAMES);
     let arg_types = input.sig.inputs.iter().map(GET_ARG_TYPES);
     let attrs = input
         .attrs
         .into_iter()
-        .filter(|attr| !attr.path().is_ident("post_hook"));
+        .filter(|attr| !attr.path().is_ident("post_hook"))
+        .collect::<Vec<_>>();
+    let attrs = undecorate_export_name(attrs, input.sig.abi.as_ref());
     let sig_type: ProxySignatureType = match attr_input {
         Ok(attr_input) => attr_input.into(),
         Err(_) => panic!("Please explictly set sig=\"known\" or sig=\"unknown\". Eg. #[post_hook(sig = \"known\")]"),
@@ -0,0 +1,325 @@
+use exe::{
+    Buffer, CCharString, DLLCharacteristics, FileCharacteristics, ImageDirectoryEntry, ImportData,
+    ImportDirectory, NTHeaders, PETranslation, TLSDirectory, VecPE, PE, RVA,
+};
+
+use crate::Result;
+
+/// Imported functions which suggest the DLL spawns threads, most likely from its `DllMain`
+const THREAD_APIS: &[&str] = &[
+    "CreateThread",
+    "CreateRemoteThread",
+    "CreateRemoteThreadEx",
+    "_beginthread",
+    "_beginthreadex",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    /// Affects loading the original DLL as `<name>_` from the proxy's init thread
+    Warning,
+    /// Worth knowing about, but doesn't get in the way of proxying
+    Note,
+}
+
+/// A loader-relevant trait found in the original DLL
+pub struct Finding {
+    pub severity: Severity,
+    pub title: &'static str,
+    pub detail: String,
+}
+
+impl Finding {
+    fn warning(title: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            title,
+            detail: detail.into(),
+        }
+    }
+
+    fn note(title: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Note,
+            title,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Audits the original DLL for traits that need special care when proxying it.
+///
+/// The proxy loads the original as `<dll_name>_` from its own init thread, after the process has already started,
+/// so anything that relies on being loaded by the loader at startup (or under its own name) gets flagged.
+pub fn audit_dll(pe_file: &VecPE, dll_name: &str) -> Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+    let (file_characteristics, dll_characteristics, image_base, is_64bit) =
+        match pe_file.get_valid_nt_headers()? {
+            NTHeaders::NTHeaders32(headers) => (
+                headers.file_header.characteristics,
+                headers.optional_header.dll_characteristics,
+                headers.optional_header.image_base as u64,
+                false,
+            ),
+            NTHeaders::NTHeaders64(headers) => (
+                headers.file_header.characteristics,
+                headers.optional_header.dll_characteristics,
+                headers.optional_header.image_base,
+                true,
+            ),
+        };
+
+    if pe_file.has_data_directory(ImageDirectoryEntry::TLS) {
+        let callbacks = match TLSDirectory::parse(pe_file)? {
+            TLSDirectory::TLS32(tls) => tls.get_callbacks(pe_file)?.len(),
+            TLSDirectory::TLS64(tls) => tls.get_callbacks(pe_file)?.len(),
+        };
+        if callbacks > 0 {
+            findings.push(Finding::warning(
+                "TLS callbacks",
+                format!(
+                    "{} TLS callback(s) will run on the proxy's init thread when the original is loaded, not at process startup. \
+                     Threads that already exist won't have seen DLL_PROCESS_ATTACH/DLL_THREAD_ATTACH for them",
+                    callbacks
+                ),
+            ));
+        } else {
+            findings.push(Finding::note(
+                "Thread local storage",
+                "The original uses static TLS, which only gets set up for existing threads lazily once it's loaded",
+            ));
+        }
+    }
+
+    if file_characteristics.contains(FileCharacteristics::RELOCS_STRIPPED)
+        || !pe_file.has_data_directory(ImageDirectoryEntry::BaseReloc)
+    {
+        findings.push(Finding::warning(
+            "No relocations",
+            format!(
+                "The original can only be loaded at its preferred base address {:#x}. \
+                 By the time the proxy loads it that range may already be taken (eg. by the proxy itself), and loading will fail",
+                image_base
+            ),
+        ));
+    } else if !dll_characteristics.contains(DLLCharacteristics::DYNAMIC_BASE) {
+        findings.push(Finding::note(
+            "No ASLR",
+            format!(
+                "The original isn't ASLR compatible, and prefers to load at {:#x}. It will be relocated if that is taken",
+                image_base
+            ),
+        ));
+    }
+
+    let imported_dlls = get_imported_dlls(pe_file)?;
+    let delay_imported_dlls = get_delay_imported_dlls(pe_file, image_base)?;
+    if !delay_imported_dlls.is_empty() {
+        findings.push(Finding::warning(
+            "Delay imports",
+            format!(
+                "Delay-loaded DLLs ({}) get loaded by whichever thread first calls into them, which may race with the proxy's init thread",
+                delay_imported_dlls.join(", ")
+            ),
+        ));
+    }
+    let imports_itself = imported_dlls
+        .iter()
+        .chain(delay_imported_dlls.iter())
+        .find(|imported_dll| imported_dll.eq_ignore_ascii_case(dll_name));
+    if let Some(imported_dll) = imports_itself {
+        findings.push(Finding::warning(
+            "Imports itself",
+            format!(
+                "The original imports from {}, which will resolve to the proxy rather than the renamed original",
+                imported_dll
+            ),
+        ));
+    }
+
+    if pe_file.get_entrypoint()?.0 != 0 {
+        let thread_apis = get_imported_functions(pe_file)?
+            .into_iter()
+            .filter(|func| THREAD_APIS.contains(&func.as_str()))
+            .collect::<Vec<_>>();
+        if !thread_apis.is_empty() {
+            findings.push(Finding::warning(
+                "DllMain may spawn threads",
+                format!(
+                    "The original has an entry point and imports {}. Threads spawned from its DllMain can't start until the proxy's \
+                     init thread releases the loader lock, so a DllMain waiting on them will deadlock every proxied call",
+                    thread_apis.join(", ")
+                ),
+            ));
+        }
+    }
+
+    // Importing itself already names the DLL, so there's no point reporting that twice
+    if imports_itself.is_none() && find_own_name(pe_file, dll_name)? {
+        findings.push(Finding::warning(
+            "References its own name",
+            format!(
+                "The original contains the string \"{}\". Lookups like GetModuleHandle(\"{}\") will find the proxy, \
+                 since the original is loaded as {}_",
+                dll_name, dll_name, dll_name
+            ),
+        ));
+    }
+
+    if dll_characteristics.contains(DLLCharacteristics::GUARD_CF) {
+        findings.push(Finding::note(
+            "Control Flow Guard",
+            "The original is built with CFG. Its exports are resolved with GetProcAddress, which keeps them valid call targets",
+        ));
+    }
+
+    if is_64bit && dll_characteristics.contains(DLLCharacteristics::HIGH_ENTROPY_VA) {
+        findings.push(Finding::note(
+            "High entropy ASLR",
+            "The original may be loaded above 4GB. Hooks must not truncate its pointers to 32 bits",
+        ));
+    }
+
+    Ok(findings)
+}
+
+/// Gets the names of the DLLs the original imports from
+fn get_imported_dlls(pe_file: &VecPE) -> Result<Vec<String>> {
+    if !pe_file.has_data_directory(ImageDirectoryEntry::Import) {
+        return Ok(Vec::new());
+    }
+    let import_directory = ImportDirectory::parse(pe_file)?;
+    Ok(import_directory
+        .get_import_map(pe_file)?
+        .into_keys()
+        .map(String::from)
+        .collect())
+}
+
+/// Gets the names of every function the original imports by name
+fn get_imported_functions(pe_file: &VecPE) -> Result<Vec<String>> {
+    if !pe_file.has_data_directory(ImageDirectoryEntry::Import) {
+        return Ok(Vec::new());
+    }
+    let import_directory = ImportDirectory::parse(pe_file)?;
+    Ok(import_directory
+        .get_import_map(pe_file)?
+        .into_values()
+        .flatten()
+        .filter_map(|import| match import {
+            ImportData::ImportByName(name) => Some(name.to_string()),
+            ImportData::Ordinal(_) => None,
+        })
+        .collect())
+}
+
+/// Gets the names of the DLLs the original delay-loads.
+///
+/// exe doesn't parse the delay import directory, so we walk the `ImgDelayDescr` entries ourselves
+fn get_delay_imported_dlls(pe_file: &VecPE, image_base: u64) -> Result<Vec<String>> {
+    if !pe_file.has_data_directory(ImageDirectoryEntry::DelayImport) {
+        return Ok(Vec::new());
+    }
+    let directory = pe_file.get_data_directory(ImageDirectoryEntry::DelayImport)?;
+    let mut offset = pe_file.translate(PETranslation::Memory(directory.virtual_address))?;
+    let mut dlls = Vec::new();
+    loop {
+        // Attributes, then the DLL name, followed by 6 more fields we don't care about
+        let descriptor = pe_file
+            .get_slice_ref::<u32>(offset, 8)
+            .map_err(exe::Error::from)?;
+        if descriptor.iter().all(|field| *field == 0) {
+            break;
+        }
+        // Old style descriptors store VAs rather than RVAs
+        let name_rva = if descriptor[0] & 1 != 0 {
+            descriptor[1]
+        } else {
+            (descriptor[1] as u64).saturating_sub(image_base) as u32
+        };
+        let name = pe_file.get_cstring(
+            pe_file.translate(PETranslation::Memory(RVA(name_rva)))?,
+            false,
+            None,
+        )?;
+        dlls.push(name.as_str()?.to_string());
+        offset += 32;
+    }
+    Ok(dlls)
+}
+
+/// Looks for the DLL's own file name, as either an ANSI or a wide string.
+///
+/// The export directory always names the DLL, so matches in there don't count
+fn find_own_name(pe_file: &VecPE, dll_name: &str) -> Result<bool> {
+    let data = pe_file.as_slice();
+    let export_name_range = if pe_file.has_data_directory(ImageDirectoryEntry::Export) {
+        let export_directory = exe::ExportDirectory::parse(pe_file)?;
+        let start = pe_file.translate(PETranslation::Memory(export_directory.name))?;
+        start..start + dll_name.len() + 1
+    } else {
+        0..0
+    };
+
+    let ansi: Vec<u8> = dll_name.to_ascii_lowercase().into_bytes();
+    let wide: Vec<u8> = ansi.iter().flat_map(|c| [*c, 0]).collect();
+    for needle in [ansi, wide] {
+        let found = data
+            .windows(needle.len())
+            .enumerate()
+            .any(|(offset, window)| {
+                !export_name_range.contains(&offset) && window.eq_ignore_ascii_case(&needle)
+            });
+        if found {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::fixture;
+
+    fn audit(name: &str, dll_name: &str) -> Vec<(Severity, &'static str)> {
+        let pe_file = VecPE::from_disk_data(fixture(name));
+        audit_dll(&pe_file, dll_name)
+            .unwrap()
+            .into_iter()
+            .map(|finding| (finding.severity, finding.title))
+            .collect()
+    }
+
+    #[test]
+    fn finds_loader_hazards() {
+        assert_eq!(
+            audit("audited.dll", "audited.dll"),
+            [
+                (Severity::Warning, "TLS callbacks"),
+                (Severity::Warning, "No relocations"),
+                (Severity::Warning, "Delay imports"),
+                (Severity::Warning, "Imports itself"),
+                (Severity::Warning, "DllMain may spawn threads"),
+                (Severity::Note, "Control Flow Guard"),
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_own_name_outside_the_export_directory() {
+        // The export directory's own name for the DLL doesn't count
+        assert_eq!(
+            audit("fixture.dll", "fixture.dll"),
+            [(Severity::Note, "High entropy ASLR")]
+        );
+        // The forwarder to `NTDLL.RtlAllocateHeap` names ntdll
+        assert_eq!(
+            audit("fixture.dll", "ntdll"),
+            [
+                (Severity::Warning, "References its own name"),
+                (Severity::Note, "High entropy ASLR"),
+            ]
+        );
+    }
+}
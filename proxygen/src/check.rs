@@ -0,0 +1,916 @@
+//! Cross-checks a proxy project's generated files, manifest and hooks against each other, and against the original DLL.
+//!
+//! These catch the drift that otherwise only shows up as a confusing build or runtime failure, eg. export indices that
+//! don't match `src/orig_exports.rs`, hooks for exports the project doesn't have, or exports that end up defined twice.
+
+use exe::Arch;
+use regex::Regex;
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    exports::ExportKind,
+    hooks::{duplicate_hooks, find_forwarders, find_hooks, Hook},
+    manifest::MANIFEST_FILE,
+    project::{target_arch, ArchExports, ProxyProject},
+    proxy::ProxyTemplates,
+    Result,
+};
+
+/// An inconsistency in a project, and where it was found
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Issue {
+    /// The file the issue is in, relative to the project
+    pub path: PathBuf,
+    /// The 1-based line the issue is on, or `None` if it's about the file as a whole
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl Issue {
+    fn new(path: impl Into<PathBuf>, line: Option<usize>, message: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            line,
+            message: message.into(),
+        }
+    }
+
+    fn at_hook(hook: &Hook, message: impl Into<String>) -> Self {
+        Self::new(&hook.path, Some(hook.line), message)
+    }
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(
+                f,
+                "{}:{}: {}",
+                self.path.to_string_lossy(),
+                line,
+                self.message
+            ),
+            None => write!(f, "{}: {}", self.path.to_string_lossy(), self.message),
+        }
+    }
+}
+
+/// Checks the project at `project_dir` for inconsistencies, returning every one found.
+///
+/// `original` is the original DLL's name and builds to check the project's exports against, at most one per arch, as
+/// read by [`DLLFile`](crate::DLLFile).
+/// Files that differ from what the manifest generates with `templates` are reported too, at their first differing line.
+pub fn check_project(
+    project_dir: &Path,
+    original: Option<&(String, Vec<ArchExports>)>,
+    templates: &ProxyTemplates,
+) -> Result<Vec<Issue>> {
+    let mut issues = Vec::new();
+    let hooks = find_hooks(project_dir)?;
+    for duplicates in duplicate_hooks(&hooks) {
+        for hook in duplicates.iter() {
+            let others: Vec<String> = duplicates
+                .iter()
+                .filter(|other| !std::ptr::eq(**other, *hook))
+                .map(|other| format!("{}:{}", other.path.to_string_lossy(), other.line))
+                .collect();
+            issues.push(Issue::at_hook(
+                hook,
+                format!(
+                    "'{}' is also hooked at {}, so it would be exported twice",
+                    hook.export_name,
+                    others.join(", ")
+                ),
+            ));
+        }
+    }
+    let intercepted_exports = hooks.iter().map(|hook| hook.export_name.clone()).collect();
+    let project = ProxyProject::read_with_intercepted_exports(project_dir, intercepted_exports)?;
+    let manifest = std::fs::read_to_string(project_dir.join(MANIFEST_FILE)).ok();
+    if manifest.is_none() {
+        issues.push(Issue::new(
+            MANIFEST_FILE,
+            None,
+            "is missing. Run `proxygen update` to create it from the generated files",
+        ));
+    }
+    let manifest = ManifestLines::new(manifest.as_deref(), &project);
+
+    check_hooks(&project, &hooks, &manifest, &mut issues);
+    check_forwarders(
+        &project,
+        &hooks,
+        &find_forwarders(project_dir)?,
+        &mut issues,
+    );
+    let multi_arch = project.other_arch.is_some();
+    for arch in project.arches().iter() {
+        check_indices(project_dir, arch, multi_arch, &manifest, &mut issues)?;
+    }
+    match original {
+        Some((dll_name, _)) if !dll_name.eq_ignore_ascii_case(&project.dll_name) => {
+            issues.push(Issue::new(
+                MANIFEST_FILE,
+                None,
+                format!("the project proxies {}, not {}", project.dll_name, dll_name),
+            ))
+        }
+        Some((_, originals)) => {
+            for original in originals.iter() {
+                check_original(&project, original, &manifest, &mut issues);
+            }
+        }
+        None => {}
+    }
+    check_generated_files(project_dir, &project, templates, &mut issues)?;
+    Ok(issues)
+}
+
+/// Finds each export's line in `proxygen.toml`, so issues with an export can point at its entry
+struct ManifestLines {
+    /// The line of each arch's entry for each export, by arch and original name
+    exports: BTreeMap<(String, String), usize>,
+    /// The line of each arch's original DLL hash, by arch
+    sha256: BTreeMap<String, usize>,
+}
+
+impl ManifestLines {
+    fn new(manifest: Option<&str>, project: &ProxyProject) -> Self {
+        let mut lines = Self {
+            exports: BTreeMap::new(),
+            sha256: BTreeMap::new(),
+        };
+        let Some(manifest) = manifest else {
+            return lines;
+        };
+        let name_re = Regex::new(r#"^name\s*=\s*"(.*)"\s*$"#).expect("valid regex");
+        let other_arch = project.other_arch.as_ref().map(|other| other.arch);
+        // The arch of the current table, and whether it's one of that arch's exports
+        let mut section = None;
+        for (i, line) in manifest.lines().enumerate() {
+            let line = line.trim();
+            match line {
+                "[original]" => section = Some((project.arch, false)),
+                "[other_arch]" => section = other_arch.map(|arch| (arch, false)),
+                "[[exports]]" => section = Some((project.arch, true)),
+                "[[other_arch.exports]]" => section = other_arch.map(|arch| (arch, true)),
+                _ if line.starts_with('[') => section = None,
+                _ => match (section, name_re.captures(line)) {
+                    (Some((arch, true)), Some(captures)) => {
+                        // Names are written as TOML basic strings, so parse them back to undo any escapes
+                        let name = toml::from_str::<toml::Table>(line)
+                            .ok()
+                            .and_then(|table| table.get("name")?.as_str().map(String::from))
+                            .unwrap_or_else(|| captures[1].to_string());
+                        lines.exports.insert((format!("{:?}", arch), name), i + 1);
+                    }
+                    (Some((arch, false)), None) if line.starts_with("sha256") => {
+                        lines.sha256.insert(format!("{:?}", arch), i + 1);
+                    }
+                    _ => {}
+                },
+            }
+        }
+        lines
+    }
+
+    /// The line of the export's entry for the given arch
+    fn line(&self, arch: Arch, export_name: &str) -> Option<usize> {
+        self.exports
+            .get(&(format!("{:?}", arch), export_name.to_string()))
+            .copied()
+    }
+
+    /// An issue with an export's entry in `proxygen.toml`
+    fn issue(&self, arch: Arch, export_name: &str, message: impl Into<String>) -> Issue {
+        Issue::new(MANIFEST_FILE, self.line(arch, export_name), message)
+    }
+}
+
+/// Whether a hook is built for the given arch, going by its `#[cfg(...)]`s.
+///
+/// Only `target_arch` predicates are evaluated, so this is `None` if the answer depends on anything else, eg. a feature
+fn built_for(hook: &Hook, arch: Arch) -> Option<bool> {
+    all(hook
+        .cfg
+        .iter()
+        .map(|cfg| match syn::parse_str::<syn::Meta>(cfg) {
+            Ok(syn::Meta::List(list)) if list.path.is_ident("cfg") => {
+                match cfg_predicates(&list).as_deref() {
+                    Ok([predicate]) => evaluate_cfg(predicate, arch),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }))
+}
+
+/// Evaluates a `cfg` predicate for the given arch, or `None` if it depends on more than `target_arch`
+fn evaluate_cfg(predicate: &syn::Meta, arch: Arch) -> Option<bool> {
+    match predicate {
+        syn::Meta::NameValue(syn::MetaNameValue {
+            path,
+            value:
+                syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(value),
+                    ..
+                }),
+            ..
+        }) if path.is_ident("target_arch") => Some(value.value() == target_arch(arch)),
+        syn::Meta::List(list) => {
+            let predicates = cfg_predicates(list).ok()?;
+            let mut results = predicates
+                .iter()
+                .map(|predicate| evaluate_cfg(predicate, arch));
+            if list.path.is_ident("all") {
+                all(results)
+            } else if list.path.is_ident("any") {
+                // `any(a, b)` is `not(all(not(a), not(b)))`
+                all(results.map(|result| result.map(|result| !result))).map(|result| !result)
+            } else if list.path.is_ident("not") && predicates.len() == 1 {
+                results.next()?.map(|result| !result)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The predicates inside `cfg(...)`, `all(...)`, `any(...)` or `not(...)`
+fn cfg_predicates(list: &syn::MetaList) -> syn::Result<Vec<syn::Meta>> {
+    list.parse_args_with(syn::punctuated::Punctuated::<syn::Meta, syn::Token![,]>::parse_terminated)
+        .map(|predicates| predicates.into_iter().collect())
+}
+
+/// Three-valued `all`: false if any result is false, otherwise unknown if any result is unknown
+fn all(results: impl Iterator<Item = Option<bool>>) -> Option<bool> {
+    let mut known = true;
+    for result in results {
+        match result {
+            Some(false) => return Some(false),
+            Some(true) => {}
+            None => known = false,
+        }
+    }
+    known.then_some(true)
+}
+
+/// Hooks have to intercept an export the project proxies, on every arch they're built for
+fn check_hooks(
+    project: &ProxyProject,
+    hooks: &[Hook],
+    manifest: &ManifestLines,
+    issues: &mut Vec<Issue>,
+) {
+    let arches = project.arches();
+    for hook in hooks.iter() {
+        let built_arches: Vec<Option<bool>> = arches
+            .iter()
+            .map(|arch| built_for(hook, arch.arch))
+            .collect();
+        // Hooks limited to an arch the project doesn't proxy are never built
+        let hooked_arches = arches
+            .iter()
+            .zip(built_arches.iter())
+            .filter(|(_, built)| **built != Some(false));
+        for (arch, built) in hooked_arches {
+            if let Some(forwarded) = arch
+                .forwarded_exports
+                .iter()
+                .find(|export| export.original == hook.export_name)
+            {
+                let location = manifest
+                    .line(arch.arch, &forwarded.original)
+                    .map_or(String::new(), |line| {
+                        format!(" ({}:{})", MANIFEST_FILE, line)
+                    });
+                issues.push(Issue::at_hook(
+                    hook,
+                    format!(
+                        "'{}' is forwarded to the original DLL{}, so hooking it too would export it twice. Stop excluding it, or remove the hook",
+                        hook.export_name, location
+                    ),
+                ));
+            } else if let Some(data) = arch.exports.iter().find(|export| {
+                export.original == hook.export_name && export.kind == ExportKind::Data
+            }) {
+                let location = manifest
+                    .line(arch.arch, &data.original)
+                    .map_or(String::new(), |line| {
+                        format!(" ({}:{})", MANIFEST_FILE, line)
+                    });
+                issues.push(Issue::at_hook(
+                    hook,
+                    format!(
+                        "'{}' is a data export{}, which is forwarded to the original DLL since a function can't stand in for a variable. Remove the hook",
+                        hook.export_name, location
+                    ),
+                ));
+            } else if !arch
+                .exports
+                .iter()
+                .any(|export| export.original == hook.export_name)
+            {
+                let message = if built.is_none() {
+                    format!(
+                        "'{}' isn't an export of the {:?} DLL, and the hook's #[cfg(...)]s don't say whether it's built for {:?} (unknown arch)",
+                        hook.export_name, arch.arch, arch.arch
+                    )
+                } else if arches.len() > 1 && built_arches.iter().all(|built| *built == Some(true))
+                {
+                    format!(
+                        "'{}' isn't an export of the {:?} DLL. Limit the hook to the arches that have it with #[cfg(target_arch = \"...\")]",
+                        hook.export_name, arch.arch
+                    )
+                } else {
+                    format!(
+                        "'{}' isn't an export of the {:?} DLL, so the hook won't build",
+                        hook.export_name, arch.arch
+                    )
+                };
+                issues.push(Issue::at_hook(hook, message));
+            }
+        }
+    }
+}
+
+/// The generated thunks in `src/proxied_exports.rs` must be for exports the project proxies, and mustn't clash with a hook
+fn check_forwarders(
+    project: &ProxyProject,
+    hooks: &[Hook],
+    forwarders: &[Hook],
+    issues: &mut Vec<Issue>,
+) {
+    let arches = project.arches();
+    let proxied: HashSet<&str> = arches
+        .iter()
+        .flat_map(|arch| arch.exports.iter())
+        .map(|export| export.original.as_str())
+        .collect();
+    for forwarder in forwarders.iter() {
+        if !proxied.contains(forwarder.export_name.as_str()) {
+            issues.push(Issue::at_hook(
+                forwarder,
+                format!(
+                    "has a thunk for '{}', which isn't in {}. Run `proxygen update`",
+                    forwarder.export_name, MANIFEST_FILE
+                ),
+            ));
+        }
+        for hook in hooks
+            .iter()
+            .filter(|hook| hook.export_name == forwarder.export_name)
+        {
+            // Whether the hook and the thunk are both built for each arch
+            let clashes: Vec<Option<bool>> = arches
+                .iter()
+                .map(|arch| {
+                    all([built_for(hook, arch.arch), built_for(forwarder, arch.arch)].into_iter())
+                })
+                .collect();
+            let unknown = if clashes.contains(&Some(true)) {
+                ""
+            } else if clashes.contains(&None) {
+                ", if the hook's #[cfg(...)]s build it for the same arch (unknown arch)"
+            } else {
+                continue;
+            };
+            issues.push(Issue::at_hook(
+                forwarder,
+                format!(
+                    "'{}' has a generated thunk, but is also hooked at {}:{}, so it would be exported twice{}. Run `proxygen update`",
+                    forwarder.export_name,
+                    hook.path.to_string_lossy(),
+                    hook.line,
+                    unknown
+                ),
+            ));
+        }
+    }
+}
+
+/// A line of `src/export_indices.rs`, eg. `pub const Index_Foo: usize = 3;`
+struct IndexConst {
+    line: usize,
+    value: usize,
+}
+
+/// `src/export_indices.rs` and `src/orig_exports.rs` have to agree with each other and with the manifest on which
+/// export is loaded into which slot of `ORIGINAL_FUNCS`
+fn check_indices(
+    project_dir: &Path,
+    arch: &ArchExports,
+    multi_arch: bool,
+    manifest: &ManifestLines,
+    issues: &mut Vec<Issue>,
+) -> Result<()> {
+    let module_path = |module: &str| match multi_arch {
+        true => Path::new("src")
+            .join(module)
+            .join(format!("{}.rs", target_arch(arch.arch))),
+        false => Path::new("src").join(format!("{}.rs", module)),
+    };
+    let indices_path = module_path("export_indices");
+    let orig_exports_path = module_path("orig_exports");
+    let (Ok(indices), Ok(orig_exports)) = (
+        std::fs::read_to_string(project_dir.join(&indices_path)),
+        std::fs::read_to_string(project_dir.join(&orig_exports_path)),
+    ) else {
+        for path in [&indices_path, &orig_exports_path] {
+            if !project_dir.join(path).is_file() {
+                issues.push(Issue::new(path, None, "is missing. Run `proxygen update`"));
+            }
+        }
+        return Ok(());
+    };
+
+    let total_re = Regex::new(r"^pub const TOTAL_EXPORTS\s*:\s*usize\s*=\s*(\d+)\s*;")?;
+    let index_re = Regex::new(r"^pub const Index_(\w+)\s*:\s*usize\s*=\s*(\d+)\s*;")?;
+    let name_re = Regex::new(r#"^\("((?:[^"\\]|\\.)*)"\s*,\s*Index_(\w+)\)\s*,"#)?;
+    let mut total = None;
+    let mut consts: BTreeMap<String, IndexConst> = BTreeMap::new();
+    let mut names: BTreeMap<String, (String, usize)> = BTreeMap::new();
+    let mut slots: BTreeMap<usize, String> = BTreeMap::new();
+    for (i, line) in indices.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.trim();
+        if let Some(captures) = total_re.captures(line) {
+            total = Some((
+                captures[1].parse::<usize>().unwrap_or(usize::MAX),
+                line_number,
+            ));
+        } else if let Some(captures) = index_re.captures(line) {
+            let value = captures[2].parse().unwrap_or(usize::MAX);
+            if let Some(other) = slots.insert(value, captures[1].to_string()) {
+                issues.push(Issue::new(
+                    &indices_path,
+                    Some(line_number),
+                    format!(
+                        "Index_{} has the same index ({}) as Index_{}",
+                        &captures[1], value, other
+                    ),
+                ));
+            }
+            consts.insert(
+                captures[1].to_string(),
+                IndexConst {
+                    line: line_number,
+                    value,
+                },
+            );
+        } else if let Some(captures) = name_re.captures(line) {
+            let name = unescape(&captures[1]);
+            names.insert(name, (captures[2].to_string(), line_number));
+        }
+    }
+    match total {
+        Some((total, line)) => {
+            for (identifier, index) in consts.iter().filter(|(_, index)| index.value >= total) {
+                issues.push(Issue::new(
+                    &indices_path,
+                    Some(index.line),
+                    format!(
+                        "Index_{} is {}, past the end of ORIGINAL_FUNCS (TOTAL_EXPORTS is {})",
+                        identifier, index.value, total
+                    ),
+                ));
+            }
+            if total != arch.exports.len() {
+                issues.push(Issue::new(
+                    &indices_path,
+                    Some(line),
+                    format!(
+                        "TOTAL_EXPORTS is {}, but {} lists {} {:?} export(s)",
+                        total,
+                        MANIFEST_FILE,
+                        arch.exports.len(),
+                        arch.arch
+                    ),
+                ));
+            }
+        }
+        None => issues.push(Issue::new(
+            &indices_path,
+            None,
+            "doesn't define TOTAL_EXPORTS",
+        )),
+    }
+
+    let load_re = Regex::new(
+        r#"^load_dll_func\(\s*Index_(\w+)\s*,\s*\w+\s*,\s*"((?:[^"\\]|\\.)*)"\s*\)\s*;"#,
+    )?;
+    let load_ordinal_re = Regex::new(
+        r#"^load_dll_func_by_ordinal\(\s*Index_(\w+)\s*,\s*\w+\s*,\s*(\d+)\s*,\s*"((?:[^"\\]|\\.)*)"\s*\)\s*;"#,
+    )?;
+    let mut loaded = HashSet::new();
+    for (i, line) in orig_exports.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.trim();
+        let (identifier, name, ordinal) = if let Some(captures) = load_re.captures(line) {
+            (captures[1].to_string(), unescape(&captures[2]), None)
+        } else if let Some(captures) = load_ordinal_re.captures(line) {
+            (
+                captures[1].to_string(),
+                unescape(&captures[3]),
+                captures[2].parse::<u16>().ok(),
+            )
+        } else {
+            continue;
+        };
+        let issue = |message: String| Issue::new(&orig_exports_path, Some(line_number), message);
+        if !consts.contains_key(&identifier) {
+            issues.push(issue(format!(
+                "loads '{}' into Index_{}, which {} doesn't define",
+                name,
+                identifier,
+                indices_path.to_string_lossy()
+            )));
+        }
+        match names.get(&name) {
+            Some((named, _)) if *named != identifier => issues.push(issue(format!(
+                "loads '{}' into Index_{}, but EXPORT_NAMES ({}) maps it to Index_{}",
+                name,
+                identifier,
+                indices_path.to_string_lossy(),
+                named
+            ))),
+            None => issues.push(issue(format!(
+                "loads '{}', which isn't in EXPORT_NAMES ({}), so hooks can't find it",
+                name,
+                indices_path.to_string_lossy()
+            ))),
+            _ => {}
+        }
+        match arch.exports.iter().find(|export| export.original == name) {
+            Some(export) if ordinal.is_some() && ordinal != export.ordinal => {
+                issues.push(issue(format!(
+                    "loads '{}' by ordinal {}, but {} has it at ordinal {}",
+                    name,
+                    ordinal.unwrap_or_default(),
+                    MANIFEST_FILE,
+                    export
+                        .ordinal
+                        .map_or("(none)".to_string(), |ordinal| ordinal.to_string())
+                )))
+            }
+            Some(_) => {}
+            None => issues.push(issue(format!(
+                "loads '{}', which isn't a {:?} export in {}",
+                name, arch.arch, MANIFEST_FILE
+            ))),
+        }
+        loaded.insert(name);
+    }
+    for export in arch.exports.iter() {
+        if !loaded.contains(&export.original) {
+            issues.push(manifest.issue(
+                arch.arch,
+                &export.original,
+                format!(
+                    "'{}' is never loaded by {}, so calls to it won't reach the original DLL",
+                    export.original,
+                    orig_exports_path.to_string_lossy()
+                ),
+            ));
+        }
+    }
+    for (name, (_, line)) in names.iter() {
+        if !arch.exports.iter().any(|export| export.original == *name) {
+            issues.push(Issue::new(
+                &indices_path,
+                Some(*line),
+                format!(
+                    "EXPORT_NAMES lists '{}', which isn't a {:?} export in {}",
+                    name, arch.arch, MANIFEST_FILE
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Undoes the escapes of a Rust string literal's contents, as written by the templates' `{:?}`
+fn unescape(literal: &str) -> String {
+    syn::parse_str::<syn::LitStr>(&format!("\"{}\"", literal))
+        .map(|literal| literal.value())
+        .unwrap_or_else(|_| literal.to_string())
+}
+
+/// The project's exports for the original DLL's arch have to match the DLL's own
+fn check_original(
+    project: &ProxyProject,
+    original: &ArchExports,
+    manifest: &ManifestLines,
+    issues: &mut Vec<Issue>,
+) {
+    let Some(arch) = project
+        .arches()
+        .into_iter()
+        .find(|arch| arch.arch == original.arch)
+    else {
+        issues.push(Issue::new(
+            MANIFEST_FILE,
+            None,
+            format!(
+                "the project doesn't proxy the {:?} DLL. Run `proxygen merge` to add it",
+                original.arch
+            ),
+        ));
+        return;
+    };
+    if let (Some(expected), Some(actual)) = (&arch.original_sha256, &original.original_sha256) {
+        if expected != actual {
+            issues.push(Issue::new(
+                MANIFEST_FILE,
+                manifest.sha256.get(&format!("{:?}", arch.arch)).copied(),
+                format!(
+                    "the {:?} DLL's SHA-256 is {}, but the project was generated from {}. Run `proxygen merge` with it",
+                    original.arch, actual, expected
+                ),
+            ));
+        }
+    }
+    let original_exports: BTreeMap<&str, _> = original
+        .exports
+        .iter()
+        .map(|export| (export.original.as_str(), export))
+        .collect();
+    let mut project_exports = HashSet::new();
+    for export in arch.exports.iter().chain(arch.forwarded_exports.iter()) {
+        project_exports.insert(export.original.as_str());
+        match original_exports.get(export.original.as_str()) {
+            None => issues.push(manifest.issue(
+                arch.arch,
+                &export.original,
+                format!(
+                    "'{}' isn't exported by the {:?} DLL, so it won't resolve to anything",
+                    export.original, arch.arch
+                ),
+            )),
+            Some(original_export) if original_export.ordinal != export.ordinal => {
+                let ordinal = |ordinal: Option<u16>| {
+                    ordinal.map_or("(none)".to_string(), |ordinal| ordinal.to_string())
+                };
+                issues.push(manifest.issue(
+                    arch.arch,
+                    &export.original,
+                    format!(
+                        "'{}' has ordinal {}, but the {:?} DLL exports it at ordinal {}",
+                        export.original,
+                        ordinal(export.ordinal),
+                        arch.arch,
+                        ordinal(original_export.ordinal)
+                    ),
+                ))
+            }
+            Some(_) => {}
+        }
+    }
+    for export in original.exports.iter() {
+        if !project_exports.contains(export.original.as_str()) {
+            issues.push(Issue::new(
+                MANIFEST_FILE,
+                None,
+                format!(
+                    "the {:?} DLL exports '{}', which the project doesn't proxy. Run `proxygen merge` to add it",
+                    original.arch, export.original
+                ),
+            ));
+        }
+    }
+}
+
+/// The generated files have to be what the manifest generates, or `proxygen update` has changes to make
+fn check_generated_files(
+    project_dir: &Path,
+    project: &ProxyProject,
+    templates: &ProxyTemplates,
+    issues: &mut Vec<Issue>,
+) -> Result<()> {
+    let mut files = project.generate_exports(templates)?;
+    // The manifest is the source of truth the other files are checked against, and rewriting it only updates the
+    // proxygen version it records
+    files.remove(MANIFEST_FILE);
+    for change in files.changes(project_dir)? {
+        let Some(old) = change.old.as_deref() else {
+            issues.push(Issue::new(
+                &change.path,
+                None,
+                "is missing. Run `proxygen update`",
+            ));
+            continue;
+        };
+        let line = old
+            .lines()
+            .zip(change.new.lines())
+            .position(|(old, new)| old != new)
+            .unwrap_or_else(|| old.lines().count().min(change.new.lines().count()))
+            + 1;
+        issues.push(Issue::new(
+            &change.path,
+            Some(line),
+            format!(
+                "differs from what {} generates, starting at this line. Run `proxygen update`",
+                MANIFEST_FILE
+            ),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        create_proxy_project, test_support::fixture_path, test_support::TempDir, DLLFile,
+        ExportFilter, GenerationOptions, ScaffoldOptions,
+    };
+
+    /// Generates a proxy for the fixture DLL in `dir`, with `filter` deciding what gets forwarded
+    fn generate(dir: &Path, filter: ExportFilter) -> (String, Vec<ArchExports>) {
+        let dll = DLLFile::new(fixture_path("fixture.dll")).unwrap();
+        let original = (
+            dll.get_dll_name().unwrap(),
+            vec![ArchExports::new(
+                dll.get_arch().unwrap(),
+                dll.parse_exports().unwrap(),
+                dll.get_sha256(),
+            )],
+        );
+        let mut options = GenerationOptions::new().unwrap();
+        options.filter = filter;
+        create_proxy_project(
+            &original.1,
+            original.0.clone(),
+            dir,
+            &options,
+            ScaffoldOptions::default(),
+        )
+        .unwrap();
+        original
+    }
+
+    fn check(dir: &Path, original: Option<&(String, Vec<ArchExports>)>) -> Vec<String> {
+        check_project(dir, original, &ProxyTemplates::new().unwrap())
+            .unwrap()
+            .iter()
+            .map(|issue| issue.to_string().replace('\\', "/"))
+            .collect()
+    }
+
+    fn hooks(dir: &Path, hooks: &str) {
+        std::fs::write(
+            dir.join("src").join("intercepted_exports.rs"),
+            format!("use proxygen_macros::*;\n{}", hooks),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn generated_project_is_consistent() {
+        let temp = TempDir::new("generated_project_is_consistent");
+        let dir = temp.path().join("proxy");
+        let original = generate(&dir, ExportFilter::default());
+        assert_eq!(check(&dir, Some(&original)), Vec::<String>::new());
+    }
+
+    #[test]
+    fn reports_hooks_the_project_cant_have() {
+        let temp = TempDir::new("reports_hooks_the_project_cant_have");
+        let dir = temp.path().join("proxy");
+        let exclude_foo = ExportFilter::new(Vec::new(), vec!["Foo".parse().unwrap()]);
+        generate(&dir, exclude_foo);
+        hooks(
+            &dir,
+            "
+#[pre_hook(sig = \"unknown\")]
+pub extern \"C\" fn Missing() {}
+
+#[pre_hook(sig = \"unknown\")]
+pub extern \"C\" fn Counter() {}
+
+#[pre_hook(sig = \"unknown\")]
+pub extern \"C\" fn Foo() {}
+
+#[cfg(target_arch = \"x86\")]
+#[pre_hook(sig = \"unknown\")]
+pub extern \"C\" fn OnlyOnX86() {}
+
+#[cfg(feature = \"extra\")]
+#[pre_hook(sig = \"unknown\")]
+pub extern \"C\" fn Extra() {}
+",
+        );
+        let issues = check(&dir, None);
+        let mut hook_issues: Vec<&str> = issues
+            .iter()
+            .map(String::as_str)
+            .filter(|issue| issue.starts_with("src/intercepted_exports.rs"))
+            .collect();
+        hook_issues.sort();
+        assert_eq!(
+            hook_issues,
+            [
+                "src/intercepted_exports.rs:10: 'Foo' is forwarded to the original DLL (proxygen.toml:19), so hooking it too would export it twice. Stop excluding it, or remove the hook",
+                "src/intercepted_exports.rs:18: 'Extra' isn't an export of the X64 DLL, and the hook's #[cfg(...)]s don't say whether it's built for X64 (unknown arch)",
+                "src/intercepted_exports.rs:4: 'Missing' isn't an export of the X64 DLL, so the hook won't build",
+                "src/intercepted_exports.rs:7: 'Counter' is a data export (proxygen.toml:13), which is forwarded to the original DLL since a function can't stand in for a variable. Remove the hook",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_hooks_clashing_with_thunks() {
+        let temp = TempDir::new("reports_hooks_clashing_with_thunks");
+        let dir = temp.path().join("proxy");
+        let original = generate(&dir, ExportFilter::default());
+        // Hooked without running `proxygen update`, so the thunk is still there
+        hooks(
+            &dir,
+            "
+#[pre_hook(sig = \"unknown\")]
+pub extern \"C\" fn Foo() {}
+",
+        );
+        let issues = check(&dir, Some(&original));
+        assert!(
+            issues.iter().any(|issue| issue.starts_with("src/proxied_exports.rs:")
+                && issue.ends_with(": 'Foo' has a generated thunk, but is also hooked at src/intercepted_exports.rs:4, so it would be exported twice. Run `proxygen update`")),
+            "{:#?}",
+            issues
+        );
+    }
+
+    #[test]
+    fn reports_a_different_original() {
+        let temp = TempDir::new("reports_a_different_original");
+        let dir = temp.path().join("proxy");
+        let (_, builds) = generate(&dir, ExportFilter::default());
+        assert_eq!(
+            check(&dir, Some(&("other.dll".into(), builds))),
+            ["proxygen.toml: the project proxies fixture.dll, not other.dll"]
+        );
+    }
+
+    fn hook_with_cfg(cfg: &[&str]) -> Hook {
+        Hook {
+            export_name: "Foo".into(),
+            path: PathBuf::from("src/intercepted_exports.rs"),
+            line: 1,
+            cfg: cfg.iter().map(|cfg| cfg.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn evaluates_target_arch_cfgs() {
+        let built = |cfg: &[&str]| {
+            let hook = hook_with_cfg(cfg);
+            (built_for(&hook, Arch::X86), built_for(&hook, Arch::X64))
+        };
+        assert_eq!(built(&[]), (Some(true), Some(true)));
+        assert_eq!(
+            built(&["cfg (target_arch = \"x86\")"]),
+            (Some(true), Some(false))
+        );
+        assert_eq!(
+            built(&["cfg (not (target_arch = \"x86\"))"]),
+            (Some(false), Some(true))
+        );
+        assert_eq!(
+            built(&["cfg (any (target_arch = \"x86\" , target_arch = \"x86_64\"))"]),
+            (Some(true), Some(true))
+        );
+        // `target_arch = "x86_64"` isn't `target_arch = "x86"` with a suffix
+        assert_eq!(
+            built(&[
+                "cfg (target_arch = \"x86_64\")",
+                "cfg (target_arch = \"x86\")"
+            ]),
+            (Some(false), Some(false))
+        );
+    }
+
+    #[test]
+    fn other_cfgs_are_unknown() {
+        let built = |cfg: &[&str]| {
+            let hook = hook_with_cfg(cfg);
+            (built_for(&hook, Arch::X86), built_for(&hook, Arch::X64))
+        };
+        assert_eq!(built(&["cfg (feature = \"extra\")"]), (None, None));
+        // Unless the arch alone decides it
+        assert_eq!(
+            built(&["cfg (all (feature = \"extra\" , target_arch = \"x86\"))"]),
+            (None, Some(false))
+        );
+        assert_eq!(
+            built(&["cfg (any (feature = \"extra\" , target_arch = \"x86\"))"]),
+            (Some(true), None)
+        );
+        assert_eq!(built(&["cfg (windows)"]), (None, None));
+        assert_eq!(built(&["not a cfg"]), (None, None));
+    }
+}
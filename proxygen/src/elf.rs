@@ -0,0 +1,230 @@
+use object::{
+    elf,
+    read::elf::{Dyn, FileHeader, Sym},
+    Architecture, Endianness, FileKind, Object,
+};
+
+use crate::{exports::ExportKind, Error, Result};
+
+/// How a dynamic symbol is bound
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Binding {
+    Global,
+    Weak,
+    /// `STB_GNU_UNIQUE`, ie. one instance per process regardless of how many objects define it
+    Unique,
+}
+
+/// The visibility of a dynamic symbol. Hidden and internal symbols aren't exported at all
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Visibility {
+    Default,
+    /// References from inside the shared object always bind to its own definition, so it can't be interposed
+    Protected,
+}
+
+/// A symbol exported from a shared object's `.dynsym`.
+///
+/// Only default and protected visibility symbols are exported, hidden and internal ones can't be linked against
+#[derive(Clone, Debug)]
+pub struct ElfExport {
+    pub name: String,
+    /// The symbol version, eg. `GLIBC_2.2.5`
+    pub version: Option<String>,
+    /// Whether this is the version the linker picks by default (`name@@VERSION` rather than `name@VERSION`)
+    pub default_version: bool,
+    pub binding: Binding,
+    pub visibility: Visibility,
+    pub kind: ExportKind,
+}
+
+impl ElfExport {
+    /// The name the proxy should use for this export.
+    ///
+    /// Non-default versions get the version appended, since the same symbol may be exported once per version
+    pub fn proxy_name(&self) -> String {
+        match &self.version {
+            Some(version) if !self.default_version => format!("{}@{}", self.name, version),
+            _ => self.name.clone(),
+        }
+    }
+}
+
+/// An ELF shared object
+pub struct SharedObject {
+    pub soname: Option<String>,
+    pub architecture: Architecture,
+    pub exports: Vec<ElfExport>,
+}
+
+impl SharedObject {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let architecture = object::File::parse(data)?.architecture();
+        let (soname, exports) = match FileKind::parse(data)? {
+            FileKind::Elf32 => parse_dynamic_symbols::<elf::FileHeader32<Endianness>>(data)?,
+            FileKind::Elf64 => parse_dynamic_symbols::<elf::FileHeader64<Endianness>>(data)?,
+            _ => {
+                return Err(Error::Malformed {
+                    format: "ELF file",
+                    message: "Not an ELF file".into(),
+                })
+            }
+        };
+        Ok(Self {
+            soname,
+            architecture,
+            exports,
+        })
+    }
+}
+
+/// Reads the `DT_SONAME` and exported symbols from `.dynsym`
+fn parse_dynamic_symbols<Elf: FileHeader<Endian = Endianness>>(
+    data: &[u8],
+) -> Result<(Option<String>, Vec<ElfExport>)> {
+    let header = Elf::parse(data)?;
+    let endian = header.endian()?;
+    let sections = header.sections(endian, data)?;
+
+    let mut soname = None;
+    if let Some((dynamic, strings_index)) = sections.dynamic(endian, data)? {
+        let strings = sections.strings(endian, data, strings_index)?;
+        if let Some(entry) = dynamic
+            .iter()
+            .find(|entry| entry.tag32(endian) == Some(elf::DT_SONAME))
+        {
+            soname = Some(String::from_utf8_lossy(entry.string(endian, strings)?).into_owned());
+        }
+    }
+
+    let symbols = sections.symbols(endian, data, elf::SHT_DYNSYM)?;
+    let versions = sections.versions(endian, data)?;
+    let mut exports = Vec::new();
+    for (index, symbol) in symbols.enumerate() {
+        // Imports are in here too, as undefined symbols
+        if symbol.is_undefined(endian) {
+            continue;
+        }
+        let binding = match symbol.st_bind() {
+            elf::STB_GLOBAL => Binding::Global,
+            elf::STB_WEAK => Binding::Weak,
+            elf::STB_GNU_UNIQUE => Binding::Unique,
+            _ => continue,
+        };
+        let visibility = match symbol.st_visibility() {
+            elf::STV_DEFAULT => Visibility::Default,
+            elf::STV_PROTECTED => Visibility::Protected,
+            _ => continue,
+        };
+        let kind = match symbol.st_type() {
+            elf::STT_FUNC | elf::STT_GNU_IFUNC => ExportKind::Code,
+            elf::STT_NOTYPE | elf::STT_OBJECT | elf::STT_COMMON | elf::STT_TLS => ExportKind::Data,
+            _ => continue,
+        };
+        let (version, default_version) = match &versions {
+            Some(versions) => {
+                let version_index = versions.version_index(endian, index);
+                (
+                    versions
+                        .version(version_index)?
+                        .map(|version| String::from_utf8_lossy(version.name()).into_owned()),
+                    !version_index.is_hidden(),
+                )
+            }
+            None => (None, true),
+        };
+        let name = String::from_utf8_lossy(symbol.name(endian, symbols.strings())?).into_owned();
+        // Version definitions get an absolute symbol of their own, which isn't a real export
+        if symbol.st_shndx(endian) == elf::SHN_ABS && version.as_ref() == Some(&name) {
+            continue;
+        }
+        exports.push(ElfExport {
+            name,
+            version,
+            default_version,
+            binding,
+            visibility,
+            kind,
+        });
+    }
+    Ok((soname, exports))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::fixture;
+
+    fn exports<'a>(shared_object: &'a SharedObject, name: &str) -> Vec<&'a ElfExport> {
+        shared_object
+            .exports
+            .iter()
+            .filter(|export| export.name == name)
+            .collect()
+    }
+
+    fn export<'a>(shared_object: &'a SharedObject, name: &str) -> &'a ElfExport {
+        match exports(shared_object, name).as_slice() {
+            [export] => export,
+            found => panic!("expected one {}, found {}", name, found.len()),
+        }
+    }
+
+    #[test]
+    fn parses_soname_and_arch() {
+        let shared_object = SharedObject::parse(&fixture("libfixture.so")).unwrap();
+        assert_eq!(shared_object.soname.as_deref(), Some("libfixture.so.1"));
+        assert_eq!(shared_object.architecture, Architecture::X86_64);
+    }
+
+    #[test]
+    fn exports_each_version_of_a_symbol() {
+        let shared_object = SharedObject::parse(&fixture("libfixture.so")).unwrap();
+        let mut names: Vec<String> = exports(&shared_object, "versioned")
+            .iter()
+            .map(|export| export.proxy_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["versioned", "versioned@VERS_1"]);
+
+        let default = exports(&shared_object, "versioned")
+            .into_iter()
+            .find(|export| export.default_version)
+            .unwrap();
+        assert_eq!(default.version.as_deref(), Some("VERS_2"));
+    }
+
+    #[test]
+    fn skips_version_definitions_and_hidden_symbols() {
+        let shared_object = SharedObject::parse(&fixture("libfixture.so")).unwrap();
+        for name in ["VERS_1", "VERS_2", "hidden_fn"] {
+            assert!(
+                exports(&shared_object, name).is_empty(),
+                "{} is exported",
+                name
+            );
+        }
+        assert_eq!(shared_object.exports.len(), 6);
+    }
+
+    #[test]
+    fn reads_binding_visibility_and_kind() {
+        let shared_object = SharedObject::parse(&fixture("libfixture.so")).unwrap();
+
+        let weak = export(&shared_object, "weak_fn");
+        assert_eq!(weak.binding, Binding::Weak);
+        assert_eq!(weak.kind, ExportKind::Code);
+
+        let protected = export(&shared_object, "protected_fn");
+        assert_eq!(protected.binding, Binding::Global);
+        assert_eq!(protected.visibility, Visibility::Protected);
+
+        let unique = export(&shared_object, "unique_obj");
+        assert_eq!(unique.binding, Binding::Unique);
+        assert_eq!(unique.kind, ExportKind::Data);
+
+        let counter = export(&shared_object, "counter");
+        assert_eq!(counter.visibility, Visibility::Default);
+        assert_eq!(counter.kind, ExportKind::Data);
+    }
+}
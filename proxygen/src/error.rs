@@ -0,0 +1,140 @@
+use std::path::PathBuf;
+
+use crate::exports::UnprovidableExport;
+
+/// Everything that can go wrong while reading exports or generating a proxy project
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// The PE file couldn't be parsed
+    #[error("Failed to parse PE file: {0}")]
+    Pe(#[from] exe::Error),
+
+    /// An import library, ELF or Mach-O file couldn't be parsed
+    #[error("Failed to parse object file: {0}")]
+    Object(#[from] object::Error),
+
+    /// One of the project templates failed to render
+    #[error("Failed to render template: {0}")]
+    Template(#[from] tera::Error),
+
+    /// One of the regexes used to read an existing project failed to compile
+    #[error(transparent)]
+    Regex(#[from] regex::Error),
+
+    /// A project's hooks aren't valid Rust, so its intercepted exports can't be detected
+    #[error("Failed to parse {}: {message}", .path.to_string_lossy())]
+    InvalidHooks { path: PathBuf, message: String },
+
+    /// The same export is hooked in more than one place
+    #[error("Export(s) hooked more than once: {}", .0.join("; "))]
+    DuplicateHooks(Vec<String>),
+
+    /// The file parsed, but its contents don't make sense
+    #[error("Malformed {format}: {message}")]
+    Malformed {
+        /// The kind of file, eg. `import library`
+        format: &'static str,
+        message: String,
+    },
+
+    /// The file targets an arch that isn't supported
+    #[error("Unsupported arch: {0}")]
+    UnsupportedArch(String),
+
+    /// The file's exports can be read, but proxies can only be generated for DLLs
+    #[error("{} is {kind}. Proxies can only be generated for DLLs", .path.to_string_lossy())]
+    NotProxyable { path: PathBuf, kind: &'static str },
+
+    /// The operation needs the DLL itself, eg. auditing its headers
+    #[error("{} is {kind}. This needs the DLL itself", .path.to_string_lossy())]
+    NotADll { path: PathBuf, kind: &'static str },
+
+    /// The DLL name couldn't be worked out from the file's path
+    #[error("Failed to get DLL name from {}", .0.to_string_lossy())]
+    InvalidDllPath(PathBuf),
+
+    #[error("{} is not a directory", .0.to_string_lossy())]
+    NotADirectory(PathBuf),
+
+    /// A template pack's `template-pack.toml` is missing or can't be parsed
+    #[error("Invalid template pack at {}: {message}", .path.to_string_lossy())]
+    InvalidTemplatePack { path: PathBuf, message: String },
+
+    /// A template pack was written for a different version of the templates' variables
+    #[error("Template pack at {} is version {version}, but this proxygen needs version {}. Export the built in templates with `proxygen templates export` and port your changes over", .path.to_string_lossy(), crate::proxy::TEMPLATE_PACK_VERSION)]
+    IncompatibleTemplatePack { path: PathBuf, version: u32 },
+
+    /// A template pack has a file that doesn't override any of the built in templates
+    #[error("{} doesn't match the name of any built in template", .0.to_string_lossy())]
+    UnknownTemplate(PathBuf),
+
+    /// Some of the original DLL's exports can't be provided by the proxy
+    #[error("{} export(s) can't be provided by the proxy", .0.len())]
+    UnprovidableExports(Vec<UnprovidableExport>),
+
+    /// Exports are forwarded to the original DLL, but it isn't loaded from anywhere the loader can find it by name
+    #[error("Data exports, and exports filtered out of the proxy, are forwarded to the original DLL by file name, but the original is loaded with '{0}'. Add `sibling` or `relative:NAME` to the strategy, or (if the DLL has no data exports) proxy every export")]
+    UnforwardableExports(String),
+
+    /// A project was requested without any original DLL to proxy
+    #[error("No original DLL was given")]
+    NoOriginals,
+
+    /// More than one build of the original DLL was given for the same arch
+    #[error("More than one {0} DLL was given. A project proxies at most one x86 and one x64 build of a DLL")]
+    DuplicateArch(String),
+
+    /// The builds of the original DLL for different arches don't share a name
+    #[error("The DLLs have different names ({0} and {1}). Every arch has to proxy the same DLL")]
+    MismatchedDllNames(String, String),
+
+    /// A new project was requested in a folder that already exists
+    #[error("Folder {} already exists", .0.to_string_lossy())]
+    ProjectExists(PathBuf),
+
+    /// Scaffolding a project would overwrite files that proxygen didn't generate
+    #[error("Refusing to overwrite existing file(s): {}", .0.iter().map(|path| path.to_string_lossy()).collect::<Vec<_>>().join(", "))]
+    WouldOverwrite(Vec<PathBuf>),
+
+    /// The project folder's name can't be used as a cargo package name
+    #[error("'{0}' is not a valid package name. Use only letters, numbers, `-` and `_`, and don't start with a number")]
+    InvalidPackageName(String),
+
+    /// An existing project was expected, but the folder doesn't exist
+    #[error("Folder {} doesn't exist. Consider creating a new proxy project instead", .0.to_string_lossy())]
+    ProjectNotFound(PathBuf),
+
+    /// The project's `proxygen.toml` can't be parsed or written
+    #[error("Invalid proxygen.toml: {0}")]
+    InvalidManifest(String),
+
+    /// An existing project was expected, but the folder has no Cargo.toml
+    #[error("Directory '{}' doesn't seem to contain a Cargo.toml file", .0.to_string_lossy())]
+    NotAProject(PathBuf),
+
+    /// `git init` failed while scaffolding a new project
+    #[error("Failed to initialize a git repository at {}. Exit code: {}", .path.to_string_lossy(), .code.map_or("None".into(), |code| code.to_string()))]
+    GitInit { path: PathBuf, code: Option<i32> },
+
+    /// Two DLLs of a workspace would get the same member folder, or one would clash with the hooks crate
+    #[error("More than one workspace member would be named '{0}'")]
+    DuplicateWorkspaceMember(String),
+
+    /// A workspace's root `Cargo.toml` couldn't be parsed
+    #[error("Failed to parse the Cargo.toml of workspace {}: {1}", .0.to_string_lossy())]
+    InvalidWorkspace(PathBuf, String),
+
+    /// None of a workspace's member proxies proxy the given DLL
+    #[error("No member of the workspace at {} proxies {0}", .1.to_string_lossy())]
+    NoWorkspaceProxy(String, PathBuf),
+
+    /// A project's `Cargo.toml` couldn't be parsed while upgrading it
+    #[error("Failed to parse {}: {1}", .0.to_string_lossy())]
+    InvalidCargoToml(PathBuf, String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -1,11 +1,54 @@
-use anyhow::{Ok, Result};
-use exe::{ExportDirectory, VecPE, PE, Arch};
-use std::{collections::BTreeSet, path::PathBuf};
+use exe::{
+    Arch, CCharString, ExportDirectory, ImageDirectoryEntry, PETranslation,
+    SectionCharacteristics, VecPE, PE, RVA,
+};
+use object::{Architecture, FileKind};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashMap},
+    path::{Path, PathBuf},
+};
 
-#[derive(Clone, PartialEq, PartialOrd, Eq, Ord)]
+use crate::{
+    elf::{Binding, SharedObject, Visibility},
+    implib::{ImportLibrary, ImportNameType},
+    macho::MachOLibrary,
+    Error, Result,
+};
+
+/// Whether an export is a function or a variable
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportKind {
+    Code,
+    Data,
+}
+
+/// An exported symbol.
+///
+/// Exports are identified by their original name only, so sets of exports can be merged regardless
+/// of how much we know about each one.
+#[derive(Clone, Debug, Serialize)]
 pub struct ExportName {
     pub original: String,
     pub cleaned: String,
+    /// The export's ordinal in the original DLL, if known
+    pub ordinal: Option<u16>,
+    /// Whether the original DLL only exports this by ordinal (`NONAME`)
+    pub noname: bool,
+    pub kind: ExportKind,
+    /// The target of a forwarded export, eg. `NTDLL.RtlAllocateHeap`, or `/usr/lib/libfoo.dylib:_bar` for Mach-O re-exports
+    pub forwarder: Option<String>,
+    /// The symbol version, for exports from ELF shared objects
+    pub version: Option<String>,
+    /// Whether the export is weakly bound (ELF) or a weak definition (Mach-O)
+    pub weak: bool,
+    /// Whether the export is bound `STB_GNU_UNIQUE` (ELF), ie. one definition is shared by the whole process
+    pub unique: bool,
+    /// Whether the export has protected visibility (ELF), so the object's own references to it can't be interposed
+    pub protected: bool,
 }
 
 impl ExportName {
@@ -14,51 +57,654 @@ impl ExportName {
         Self {
             original: func_name.into(),
             cleaned: clean_func_name(func_name),
+            ordinal: None,
+            noname: false,
+            kind: ExportKind::Code,
+            forwarder: None,
+            version: None,
+            weak: false,
+            unique: false,
+            protected: false,
         }
     }
+
+    /// The export's demangled name, if it looks mangled.
+    ///
+    /// Itanium C++ names (`_Z...`, or `__Z...` on Mach-O) are fully demangled. MSVC C++ names are only undecorated
+    /// down to their qualified name, eg. `?Foo@Bar@@QEAAXXZ` becomes `Bar::Foo`, and stdcall/fastcall decorations
+    /// are stripped, eg. `_Foo@12` becomes `Foo`.
+    pub fn demangled(&self) -> Option<String> {
+        // Versioned ELF symbols are listed as `name@VERSION`
+        let name = match self.version {
+            Some(_) => self.original.split('@').next()?,
+            None => self.original.as_str(),
+        };
+        let itanium = name.strip_prefix("__Z").or_else(|| name.strip_prefix("_Z"));
+        if let Some(itanium) = itanium {
+            return cpp_demangle::Symbol::new(format!("_Z{}", itanium))
+                .ok()?
+                .demangle(&cpp_demangle::DemangleOptions::default())
+                .ok();
+        }
+        if let Some(decorated) = name.strip_prefix('?') {
+            // Special members like constructors and operators (`??0`, `??_7`) aren't undecorated
+            if decorated.starts_with('?') {
+                return None;
+            }
+            let qualified = decorated.split("@@").next()?;
+            let mut scopes: Vec<&str> = qualified.split('@').collect();
+            scopes.reverse();
+            return Some(scopes.join("::"));
+        }
+        // `_Foo@8` is stdcall and `@Foo@8` is fastcall
+        let rest = name.strip_prefix('_').or_else(|| name.strip_prefix('@'))?;
+        let (undecorated, arg_bytes) = rest.rsplit_once('@')?;
+        if undecorated.is_empty()
+            || undecorated.contains('@')
+            || arg_bytes.is_empty()
+            || !arg_bytes.bytes().all(|b| b.is_ascii_digit())
+        {
+            return None;
+        }
+        Some(undecorated.to_string())
+    }
+}
+
+impl PartialEq for ExportName {
+    fn eq(&self, other: &Self) -> bool {
+        self.original == other.original
+    }
 }
 
-/// Cleans up exported function names
+impl Eq for ExportName {}
+
+impl PartialOrd for ExportName {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ExportName {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.original.cmp(&other.original)
+    }
+}
+
+/// Cleans up exported function names, so they can be used as Rust identifiers
 fn clean_func_name(func_name: &str) -> String {
     // TODO: In the future it'd probably be better to properly demangle symbols
-    func_name
+    let mut cleaned: String = func_name
         .chars()
         .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
-        .collect()
+        .collect();
+    if cleaned.is_empty() || cleaned.starts_with(|c: char| c.is_ascii_digit()) || cleaned == "_" {
+        cleaned.insert(0, '_');
+    }
+    if RUST_KEYWORDS.contains(&cleaned.as_str()) {
+        cleaned.push('_');
+    }
+    cleaned
+}
+
+/// Keywords that can't be used as function names in the generated project
+const RUST_KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
+    "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl",
+    "in", "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub",
+    "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "try", "type",
+    "typeof", "union", "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+/// Prefix of every symbol a generated proxy exports for its own use
+pub const RESERVED_SYMBOL_PREFIX: &str = "__proxygen_";
+
+/// Why a generated proxy can't provide one of the original DLL's exports
+#[derive(Clone, Debug)]
+pub enum UnprovidableReason {
+    /// The export uses proxygen's reserved symbol prefix, eg. the original is itself a proxygen proxy
+    Reserved,
+    /// The export's name contains a `"`, which can't be quoted in `module.def`
+    Unquotable,
+    /// The export's cleaned identifier is already taken by another export
+    DuplicateIdentifier(String),
+    /// The project's `lib.rs` predates support for proxying `DllMain`
+    DllMainUnsupported,
+    /// The export has to be forwarded to the original DLL, but the loading strategy (given here) doesn't load it from
+    /// anywhere the loader could find it by name
+    Unforwardable(String),
 }
 
+impl std::fmt::Display for UnprovidableReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnprovidableReason::Reserved => write!(
+                f,
+                "it uses proxygen's reserved `{}` symbol prefix",
+                RESERVED_SYMBOL_PREFIX
+            ),
+            UnprovidableReason::Unquotable => {
+                write!(f, "its name contains a `\"`, which module.def can't quote")
+            }
+            UnprovidableReason::DuplicateIdentifier(other) => {
+                write!(f, "its Rust identifier is already used by `{}`", other)
+            }
+            UnprovidableReason::DllMainUnsupported => write!(
+                f,
+                "the project's lib.rs defines its own DllMain without forwarding to the original"
+            ),
+            UnprovidableReason::Unforwardable(loading) => write!(
+                f,
+                "it's forwarded to the original DLL by file name, but the original is loaded with '{}'",
+                loading
+            ),
+        }
+    }
+}
+
+/// An export that a generated proxy isn't able to provide, and why
+#[derive(Clone, Debug)]
+pub struct UnprovidableExport {
+    pub export: ExportName,
+    pub reason: UnprovidableReason,
+}
+
+/// Finds the exports that a generated proxy isn't able to provide
+pub fn find_unprovidable_exports(exports: &BTreeSet<ExportName>) -> Vec<UnprovidableExport> {
+    let mut unprovidable = Vec::new();
+    let mut identifiers: HashMap<&str, &str> = HashMap::new();
+    for export in exports {
+        if export.original.starts_with(RESERVED_SYMBOL_PREFIX) {
+            unprovidable.push(UnprovidableExport {
+                export: export.clone(),
+                reason: UnprovidableReason::Reserved,
+            });
+        } else if export.original.contains('"') {
+            unprovidable.push(UnprovidableExport {
+                export: export.clone(),
+                reason: UnprovidableReason::Unquotable,
+            });
+        } else if let Some(other) = identifiers.get(export.cleaned.as_str()) {
+            unprovidable.push(UnprovidableExport {
+                export: export.clone(),
+                reason: UnprovidableReason::DuplicateIdentifier(other.to_string()),
+            });
+        } else {
+            identifiers.insert(&export.cleaned, &export.original);
+        }
+    }
+    unprovidable
+}
+
+enum DLLContents {
+    PE(VecPE),
+    ImportLibrary(ImportLibrary),
+    SharedObject(SharedObject),
+    MachO(MachOLibrary),
+}
+
+/// A file we can read exports from. Either a PE file, an import library for one, an ELF shared object,
+/// or a (possibly fat) Mach-O dylib.
 pub struct DLLFile {
     path: PathBuf,
-    pe_file: VecPE,
+    contents: DLLContents,
+    /// Hex encoded SHA-256 of the file
+    sha256: String,
 }
 
 impl DLLFile {
-    pub fn new(path: &PathBuf) -> Result<Self> {
-        let pe_file = VecPE::from_disk_file(path)?;
+    /// Reads and parses the file at the given path
+    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let data = std::fs::read(path)?;
+        let sha256 = Sha256::digest(&data)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        let contents = match FileKind::parse(data.as_slice()).ok() {
+            Some(FileKind::Archive) => DLLContents::ImportLibrary(ImportLibrary::parse(&data)?),
+            Some(FileKind::Elf32 | FileKind::Elf64) => {
+                DLLContents::SharedObject(SharedObject::parse(&data)?)
+            }
+            Some(
+                FileKind::MachO32
+                | FileKind::MachO64
+                | FileKind::MachOFat32
+                | FileKind::MachOFat64,
+            ) => DLLContents::MachO(MachOLibrary::parse(&data)?),
+            _ => DLLContents::PE(VecPE::from_disk_data(data)),
+        };
         Ok(Self {
-            path: path.clone(),
-            pe_file: pe_file,
+            path: path.to_path_buf(),
+            contents,
+            sha256,
         })
     }
 
-    pub fn get_exports(&self) -> Result<BTreeSet<ExportName>> {
-        println!("Getting exports for {}", self.path.to_string_lossy());
-        println!("Detected arch: {:?}", self.pe_file.get_arch()?);
-        let export_directory = ExportDirectory::parse(&self.pe_file)?;
-        Ok(export_directory
-            .get_export_map(&self.pe_file)?
-            .into_iter()
-            .map(|(func, _)| ExportName::new(func))
-            .filter(|f| {
-                f.cleaned != "DllMain"
-                    && f.cleaned != "ORIGINAL_FUNCS"
-                    && f.cleaned != "ORIG_FUNCS_PTR"
-                    && f.cleaned != "wait_dll_proxy_init"
-            })
-            .collect())
+    /// The path the file was read from
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the file is an import library, rather than the DLL itself
+    pub fn is_import_library(&self) -> bool {
+        matches!(self.contents, DLLContents::ImportLibrary(_))
+    }
+
+    /// The hex encoded SHA-256 of the DLL, which isn't known when reading an import library
+    pub fn get_sha256(&self) -> Option<&str> {
+        match self.contents {
+            DLLContents::ImportLibrary(_) => None,
+            _ => Some(&self.sha256),
+        }
+    }
+
+    /// Gets the file's exports
+    pub fn parse_exports(&self) -> Result<BTreeSet<ExportName>> {
+        match &self.contents {
+            DLLContents::PE(pe_file) => {
+                if !pe_file.has_data_directory(ImageDirectoryEntry::Export) {
+                    return Ok(BTreeSet::new());
+                }
+                let export_directory = ExportDirectory::parse(pe_file)?;
+                let export_range = pe_file.get_data_directory(ImageDirectoryEntry::Export)?;
+                let export_start = export_range.virtual_address.0;
+                let export_end = export_start.checked_add(export_range.size).ok_or_else(|| {
+                    malformed("Export directory extends past the end of the address space")
+                })?;
+                let functions = export_directory.get_functions(pe_file)?;
+                let names = export_directory.get_names(pe_file)?;
+                let name_ordinals = export_directory.get_name_ordinals(pe_file)?;
+                let sections = pe_file.get_section_table()?;
+                let mut export_names: HashMap<usize, String> = HashMap::new();
+                for (name, index) in names.iter().zip(name_ordinals) {
+                    if *index as usize >= functions.len() {
+                        return Err(malformed(
+                            "Export name refers to a function past the end of the address table",
+                        ));
+                    }
+                    let name = pe_file.get_cstring(
+                        pe_file.translate(PETranslation::Memory(*name))?,
+                        false,
+                        None,
+                    )?;
+                    export_names.insert(*index as usize, name.as_str()?.into());
+                }
+                let mut exports = BTreeSet::new();
+                // Walk the whole address table, since exports without a name (`NONAME`) are only in there
+                for (index, thunk) in functions.iter().enumerate() {
+                    let rva = thunk.0;
+                    let ordinal = u32::try_from(index)
+                        .ok()
+                        .and_then(|index| export_directory.base.checked_add(index))
+                        .and_then(|ordinal| u16::try_from(ordinal).ok())
+                        .ok_or_else(|| malformed("Export ordinal out of range"))?;
+                    let mut export = match export_names.get(&index) {
+                        Some(name) => ExportName::new(name),
+                        // Unused slots between ordinals are left zeroed
+                        None if rva == 0 => continue,
+                        None => {
+                            let mut export =
+                                ExportName::new(&noname_export_name(ordinal, &export_names));
+                            export.noname = true;
+                            export
+                        }
+                    };
+                    export.ordinal = Some(ordinal);
+                    if (export_start..export_end).contains(&rva) {
+                        // Forwarder strings live inside the export directory itself
+                        let forwarder = pe_file.get_cstring(
+                            pe_file.translate(PETranslation::Memory(RVA(rva)))?,
+                            false,
+                            None,
+                        )?;
+                        export.forwarder = Some(forwarder.as_str()?.into());
+                    } else if sections.iter().any(|section| {
+                        let start = section.virtual_address.0;
+                        (start..start.saturating_add(section.virtual_size)).contains(&rva)
+                            && !section
+                                .characteristics
+                                .contains(SectionCharacteristics::MEM_EXECUTE)
+                    }) {
+                        // Nothing in a non-executable section can be called
+                        export.kind = ExportKind::Data;
+                    }
+                    exports.insert(export);
+                }
+                Ok(exports)
+            }
+            DLLContents::ImportLibrary(import_library) => Ok(import_library
+                .entries
+                .iter()
+                .map(|entry| {
+                    let mut export = ExportName::new(entry.proxy_name(import_library.arch));
+                    export.kind = entry.kind;
+                    if entry.name_type == ImportNameType::Ordinal {
+                        export.ordinal = Some(entry.ordinal_or_hint);
+                        export.noname = true;
+                    }
+                    export
+                })
+                .collect()),
+            DLLContents::SharedObject(shared_object) => Ok(shared_object
+                .exports
+                .iter()
+                .map(|symbol| {
+                    let mut export = ExportName::new(&symbol.proxy_name());
+                    export.kind = symbol.kind;
+                    export.version = symbol.version.clone();
+                    // Unique symbols are still strong definitions, they just can't be duplicated between objects
+                    export.weak = symbol.binding == Binding::Weak;
+                    export.unique = symbol.binding == Binding::Unique;
+                    export.protected = symbol.visibility == Visibility::Protected;
+                    export
+                })
+                .collect()),
+            // Fat binaries usually export the same symbols from every slice, so merge them
+            DLLContents::MachO(library) => Ok(library
+                .slices
+                .iter()
+                .flat_map(|slice| slice.exports.iter())
+                .map(|symbol| {
+                    let mut export = ExportName::new(&symbol.name);
+                    export.kind = symbol.kind;
+                    export.weak = symbol.weak;
+                    export.forwarder = symbol
+                        .reexport
+                        .as_ref()
+                        .map(|(dylib, name)| format!("{}:{}", dylib, name));
+                    export
+                })
+                .collect()),
+        }
     }
 
     pub fn get_arch(&self) -> Result<Arch> {
-        Ok(self.pe_file.get_arch()?)
+        match &self.contents {
+            DLLContents::PE(pe_file) => Ok(pe_file.get_arch()?),
+            DLLContents::ImportLibrary(import_library) => Ok(import_library.arch),
+            DLLContents::SharedObject(_) | DLLContents::MachO(_) => Err(Error::NotProxyable {
+                path: self.path.clone(),
+                kind: self.get_kind(),
+            }),
+        }
+    }
+
+    /// Describes what kind of file this is, eg. `an import library`
+    pub fn get_kind(&self) -> &'static str {
+        match &self.contents {
+            DLLContents::PE(_) => "a PE file",
+            DLLContents::ImportLibrary(_) => "an import library",
+            DLLContents::SharedObject(_) => "an ELF shared object",
+            DLLContents::MachO(_) => "a Mach-O dylib",
+        }
+    }
+
+    /// Gets a description of the file's arch(s). Unlike `get_arch`, this also works for files proxies can't be generated for
+    pub fn get_arch_name(&self) -> Result<String> {
+        match &self.contents {
+            DLLContents::SharedObject(shared_object) => {
+                Ok(format!("{:?}", shared_object.architecture))
+            }
+            DLLContents::MachO(library) => Ok(library
+                .slices
+                .iter()
+                .map(|slice| format!("{:?}", slice.architecture))
+                .collect::<Vec<_>>()
+                .join(", ")),
+            _ => Ok(format!("{:?}", self.get_arch()?)),
+        }
     }
-}
\ No newline at end of file
+
+    /// Gets the dylibs that a Mach-O dylib re-exports everything from, with the arch of the slice re-exporting them
+    pub fn get_reexported_dylibs(&self) -> Vec<(Architecture, &str)> {
+        match &self.contents {
+            DLLContents::MachO(library) => library
+                .slices
+                .iter()
+                .flat_map(|slice| {
+                    slice
+                        .reexported_dylibs
+                        .iter()
+                        .map(|dylib| (slice.architecture, dylib.as_str()))
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Gets the underlying PE file, for anything that needs more than its exports
+    pub fn get_pe(&self) -> Result<&VecPE> {
+        match &self.contents {
+            DLLContents::PE(pe_file) => Ok(pe_file),
+            _ => Err(Error::NotADll {
+                path: self.path.clone(),
+                kind: self.get_kind(),
+            }),
+        }
+    }
+
+    /// Returns whether the file carries an Authenticode signature. The signature itself isn't verified.
+    pub fn is_signed(&self) -> Result<bool> {
+        match &self.contents {
+            DLLContents::PE(pe_file) => {
+                // The security directory holds a file offset rather than an RVA, so just check it's set
+                let security = pe_file.get_data_directory(ImageDirectoryEntry::Security)?;
+                Ok(security.virtual_address.0 != 0 && security.size != 0)
+            }
+            DLLContents::ImportLibrary(_)
+            | DLLContents::SharedObject(_)
+            | DLLContents::MachO(_) => Ok(false),
+        }
+    }
+
+    /// Gets the name of the DLL the exports belong to.
+    ///
+    /// For import libraries this is the DLL named inside the library, for shared objects it's the `DT_SONAME`
+    /// (if set), for dylibs it's the last component of the install name (if set), otherwise it's the file name.
+    pub fn get_dll_name(&self) -> Result<String> {
+        match &self.contents {
+            DLLContents::SharedObject(SharedObject {
+                soname: Some(soname),
+                ..
+            }) => Ok(soname.clone()),
+            DLLContents::MachO(library)
+                if library.slices.iter().any(|slice| slice.install_name.is_some()) =>
+            {
+                let install_name = library
+                    .slices
+                    .iter()
+                    .find_map(|slice| slice.install_name.as_deref())
+                    .unwrap_or_default();
+                Ok(install_name
+                    .rsplit('/')
+                    .next()
+                    .unwrap_or(install_name)
+                    .to_string())
+            }
+            DLLContents::PE(_) | DLLContents::SharedObject(_) | DLLContents::MachO(_) => self
+                .path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map(String::from)
+                .ok_or_else(|| Error::InvalidDllPath(self.path.clone())),
+            DLLContents::ImportLibrary(import_library) => Ok(import_library.dll_name.clone()),
+        }
+    }
+}
+
+/// The name an export that the DLL only exports by ordinal is proxied under, eg. `Ordinal12`.
+///
+/// The name itself is never exported, it's only needed for the proxy's thunk and `module.def`
+fn noname_export_name(ordinal: u16, export_names: &HashMap<usize, String>) -> String {
+    let mut name = format!("Ordinal{}", ordinal);
+    while export_names.values().any(|other| *other == name) {
+        name.insert(0, '_');
+    }
+    name
+}
+
+fn malformed(message: impl Into<String>) -> Error {
+    Error::Malformed {
+        format: "PE file",
+        message: message.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::fixture_path;
+
+    fn parse(fixture: &str) -> (DLLFile, BTreeSet<ExportName>) {
+        let file = DLLFile::new(fixture_path(fixture)).unwrap();
+        let exports = file.parse_exports().unwrap();
+        (file, exports)
+    }
+
+    fn export<'a>(exports: &'a BTreeSet<ExportName>, name: &str) -> &'a ExportName {
+        exports
+            .get(&ExportName::new(name))
+            .unwrap_or_else(|| panic!("no export {}", name))
+    }
+
+    #[test]
+    fn reads_pe_exports() {
+        let (file, exports) = parse("fixture.dll");
+        assert_eq!(file.get_arch().unwrap(), Arch::X64);
+        assert_eq!(file.get_dll_name().unwrap(), "fixture.dll");
+        assert_eq!(file.get_sha256().map(str::len), Some(64));
+
+        let foo = export(&exports, "Foo");
+        assert_eq!(foo.ordinal, Some(1));
+        assert_eq!(foo.kind, ExportKind::Code);
+        assert!(!foo.noname);
+
+        assert_eq!(export(&exports, "Counter").kind, ExportKind::Data);
+        assert_eq!(
+            export(&exports, "HeapAlloc").forwarder.as_deref(),
+            Some("NTDLL.RtlAllocateHeap")
+        );
+        assert_eq!(export(&exports, "_Bar@8").ordinal, Some(5));
+    }
+
+    #[test]
+    fn lists_unnamed_pe_exports() {
+        let (_, exports) = parse("fixture.dll");
+        assert_eq!(exports.len(), 5);
+        let unnamed = export(&exports, "Ordinal4");
+        assert!(unnamed.noname);
+        assert_eq!(unnamed.ordinal, Some(4));
+    }
+
+    #[test]
+    fn noname_exports_avoid_real_names() {
+        let names = HashMap::from([(0, "Ordinal3".to_string()), (1, "_Ordinal3".to_string())]);
+        assert_eq!(noname_export_name(3, &names), "__Ordinal3");
+        assert_eq!(noname_export_name(4, &names), "Ordinal4");
+    }
+
+    #[test]
+    fn reads_import_library_exports() {
+        let (file, exports) = parse("foo.lib");
+        assert!(file.is_import_library());
+        assert_eq!(file.get_dll_name().unwrap(), "foo.dll");
+        assert_eq!(file.get_sha256(), None);
+
+        let hidden = export(&exports, "Hidden");
+        assert!(hidden.noname);
+        assert_eq!(hidden.ordinal, Some(7));
+        assert_eq!(export(&exports, "SomeData").kind, ExportKind::Data);
+        assert_eq!(export(&exports, "Foo").ordinal, None);
+    }
+
+    #[test]
+    fn reads_shared_object_exports() {
+        let (file, exports) = parse("libfixture.so");
+        assert_eq!(file.get_dll_name().unwrap(), "libfixture.so.1");
+        assert!(matches!(file.get_arch(), Err(Error::NotProxyable { .. })));
+
+        let old = export(&exports, "versioned@VERS_1");
+        assert_eq!(old.version.as_deref(), Some("VERS_1"));
+        assert_eq!(old.demangled(), None);
+        assert_eq!(
+            export(&exports, "versioned").version.as_deref(),
+            Some("VERS_2")
+        );
+        assert!(export(&exports, "weak_fn").weak);
+        assert!(export(&exports, "protected_fn").protected);
+        let unique = export(&exports, "unique_obj");
+        assert!(unique.unique && !unique.weak);
+        assert_eq!(unique.kind, ExportKind::Data);
+    }
+
+    #[test]
+    fn merges_dylib_slices() {
+        let (file, exports) = parse("fixture.dylib");
+        assert_eq!(file.get_dll_name().unwrap(), "libfixture.dylib");
+        assert_eq!(file.get_arch_name().unwrap(), "X86_64, Aarch64");
+        assert_eq!(
+            file.get_reexported_dylibs(),
+            [
+                (Architecture::X86_64, "/usr/lib/libother.dylib"),
+                (Architecture::Aarch64, "/usr/lib/libother.dylib"),
+            ]
+        );
+        assert_eq!(exports.len(), 7);
+        assert_eq!(
+            export(&exports, "_strdup_alias").forwarder.as_deref(),
+            Some("/usr/lib/libSystem.B.dylib:_strdup")
+        );
+        assert!(export(&exports, "_weak").weak);
+    }
+
+    #[test]
+    fn demangles_export_names() {
+        let demangled = |name: &str| ExportName::new(name).demangled();
+        assert_eq!(demangled("?Foo@Bar@@QEAAXXZ").as_deref(), Some("Bar::Foo"));
+        assert_eq!(demangled("??0Bar@@QEAA@XZ"), None);
+        assert_eq!(demangled("_Foo@12").as_deref(), Some("Foo"));
+        assert_eq!(demangled("@Foo@8").as_deref(), Some("Foo"));
+        assert_eq!(demangled("_Foo@bar"), None);
+        assert_eq!(demangled("_ZN3foo3barEv").as_deref(), Some("foo::bar()"));
+        assert_eq!(demangled("__ZN3foo3barEv").as_deref(), Some("foo::bar()"));
+        assert_eq!(demangled("Foo"), None);
+    }
+
+    #[test]
+    fn cleans_names_into_identifiers() {
+        assert_eq!(ExportName::new("_Bar@8").cleaned, "_Bar_8");
+        assert_eq!(ExportName::new("3D").cleaned, "_3D");
+        assert_eq!(ExportName::new("type").cleaned, "type_");
+    }
+
+    #[test]
+    fn finds_unprovidable_exports() {
+        let exports: BTreeSet<ExportName> = [
+            "Foo@8",
+            "Foo_8",
+            "__proxygen_ORIG_FUNCS_PTR",
+            "Bar",
+            "Baz\"",
+        ]
+        .into_iter()
+        .map(ExportName::new)
+        .collect();
+        let unprovidable = find_unprovidable_exports(&exports);
+        assert_eq!(unprovidable.len(), 3);
+        assert!(matches!(
+            unprovidable[0].reason,
+            UnprovidableReason::Unquotable
+        ));
+        assert!(matches!(
+            &unprovidable[1],
+            UnprovidableExport {
+                export,
+                reason: UnprovidableReason::DuplicateIdentifier(other),
+            } if export.original == "Foo_8" && other == "Foo@8"
+        ));
+        assert!(matches!(
+            unprovidable[2].reason,
+            UnprovidableReason::Reserved
+        ));
+    }
+}
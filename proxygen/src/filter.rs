@@ -0,0 +1,229 @@
+use regex::Regex;
+use std::str::FromStr;
+
+use crate::{exports::ExportName, Error};
+
+/// A pattern matched against export names.
+///
+/// Patterns are globs (`*`, `?` and `[...]`) by default, or regexes when prefixed with `re:`.
+/// Either way, the whole name has to match.
+#[derive(Clone, Debug)]
+pub struct ExportPattern {
+    source: String,
+    regex: Regex,
+}
+
+impl ExportPattern {
+    pub fn is_match(&self, name: &str) -> bool {
+        self.regex.is_match(name)
+    }
+}
+
+impl std::fmt::Display for ExportPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl FromStr for ExportPattern {
+    type Err = Error;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        let regex = match pattern.strip_prefix("re:") {
+            Some(regex) => format!("^(?:{})$", regex),
+            None => format!("^{}$", glob_to_regex(pattern)),
+        };
+        Ok(Self {
+            source: pattern.into(),
+            regex: Regex::new(&regex)?,
+        })
+    }
+}
+
+/// Translates a glob into the equivalent regex
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::new();
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '[' => {
+                // The glob as written, in case the class turns out to be unterminated
+                let mut raw = String::from('[');
+                let mut class = String::new();
+                if chars.next_if_eq(&'!').is_some() {
+                    raw.push('!');
+                    class.push('^');
+                }
+                // A `]` right at the start is a member of the class rather than its end, as in glob(7)
+                if chars.next_if_eq(&']').is_some() {
+                    raw.push(']');
+                    class.push_str("\\]");
+                }
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    raw.push(c);
+                    if c == ']' {
+                        closed = true;
+                        break;
+                    }
+                    if matches!(c, '\\' | '[' | '^' | '&' | '~') {
+                        class.push('\\');
+                    }
+                    class.push(c);
+                }
+                if closed {
+                    regex.push('[');
+                    regex.push_str(&class);
+                    regex.push(']');
+                } else {
+                    // An unterminated class is just a literal
+                    regex.push_str(&regex::escape(&raw));
+                }
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex
+}
+
+/// Decides which exports get a thunk in the proxy, and which get forwarded straight to the original DLL.
+///
+/// An export is included if it matches any include pattern (or there are none), and no exclude pattern.
+/// Patterns are tried against both the export's original name and its demangled form.
+#[derive(Clone, Debug, Default)]
+pub struct ExportFilter {
+    pub include: Vec<ExportPattern>,
+    pub exclude: Vec<ExportPattern>,
+}
+
+impl ExportFilter {
+    pub fn new(include: Vec<ExportPattern>, exclude: Vec<ExportPattern>) -> Self {
+        Self { include, exclude }
+    }
+
+    /// Whether the filter has no patterns at all, ie. includes everything
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Whether the export should get a thunk in the proxy
+    pub fn includes(&self, export: &ExportName) -> bool {
+        let demangled = export.demangled();
+        let matches = |pattern: &ExportPattern| {
+            pattern.is_match(&export.original)
+                || demangled
+                    .as_deref()
+                    .is_some_and(|demangled| pattern.is_match(demangled))
+        };
+        (self.include.is_empty() || self.include.iter().any(matches))
+            && !self.exclude.iter().any(matches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Arch, ProxyProject};
+
+    fn pattern(pattern: &str) -> ExportPattern {
+        pattern.parse().unwrap()
+    }
+
+    fn filter(include: &[&str], exclude: &[&str]) -> ExportFilter {
+        ExportFilter::new(
+            include.iter().map(|p| pattern(p)).collect(),
+            exclude.iter().map(|p| pattern(p)).collect(),
+        )
+    }
+
+    fn names(exports: &std::collections::BTreeSet<ExportName>) -> Vec<&str> {
+        exports
+            .iter()
+            .map(|export| export.original.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn globs_match_whole_names() {
+        assert!(pattern("Get*").is_match("GetFileVersionInfoA"));
+        assert!(!pattern("Get*").is_match("VerGetFoo"));
+        assert!(pattern("Foo?").is_match("FooA"));
+        assert!(!pattern("Foo?").is_match("Foo"));
+        assert!(pattern("Foo.Bar").is_match("Foo.Bar"));
+        assert!(!pattern("Foo.Bar").is_match("FooxBar"));
+    }
+
+    #[test]
+    fn globs_support_character_classes() {
+        assert!(pattern("Foo[AW]").is_match("FooW"));
+        assert!(!pattern("Foo[AW]").is_match("FooX"));
+        assert!(pattern("Foo[!AW]").is_match("FooX"));
+        assert!(!pattern("Foo[!AW]").is_match("FooA"));
+        // An unterminated class is matched literally
+        assert!(pattern("Foo[A").is_match("Foo[A"));
+        assert!(pattern("Foo[!A").is_match("Foo[!A"));
+        assert!(pattern("Foo[\\x").is_match("Foo[\\x"));
+        // A leading `]` is a member of the class, so `[]` and `[!]` are unterminated
+        assert!(pattern("Foo[]]").is_match("Foo]"));
+        assert!(pattern("Foo[!]]").is_match("FooA"));
+        assert!(!pattern("Foo[!]]").is_match("Foo]"));
+        assert!(pattern("Foo[]").is_match("Foo[]"));
+        assert!(pattern("Foo[!]").is_match("Foo[!]"));
+        assert!(pattern("Foo[&~]").is_match("Foo&"));
+    }
+
+    #[test]
+    fn regexes_match_whole_names() {
+        assert!(pattern("re:Get.*(A|W)").is_match("GetFileVersionInfoW"));
+        assert!(!pattern("re:Get").is_match("GetFoo"));
+        assert!("re:(".parse::<ExportPattern>().is_err());
+        assert_eq!(pattern("re:Get.*").to_string(), "re:Get.*");
+    }
+
+    #[test]
+    fn includes_and_excludes() {
+        let filter = filter(&["Get*", "Ver*"], &["*W"]);
+        assert!(filter.includes(&ExportName::new("GetFooA")));
+        assert!(!filter.includes(&ExportName::new("GetFooW")));
+        assert!(!filter.includes(&ExportName::new("SetFoo")));
+        assert!(ExportFilter::default().includes(&ExportName::new("SetFoo")));
+        assert!(ExportFilter::default().is_empty());
+    }
+
+    #[test]
+    fn matches_demangled_names() {
+        let filter = filter(&["Bar::*"], &[]);
+        assert!(filter.includes(&ExportName::new("?Foo@Bar@@QEAAXXZ")));
+        assert!(filter.includes(&ExportName::new("_ZN3Bar3fooEv")));
+        assert!(!filter.includes(&ExportName::new("?Foo@Baz@@QEAAXXZ")));
+    }
+
+    #[test]
+    fn apply_forwards_excluded_exports() {
+        let exports = ["DllMain", "GetFooA", "GetFooW", "Hooked", "SetFoo"]
+            .into_iter()
+            .map(ExportName::new)
+            .collect();
+        let mut project = ProxyProject::new("proxy", "foo.dll", Arch::X64, exports);
+        project.intercepted_exports.insert("Hooked".into());
+
+        project.apply_filter(&filter(&["Get*"], &["*W"]));
+        // Hooks and DllMain are always proxied, since the proxy has to provide them itself
+        assert_eq!(names(&project.exports), ["DllMain", "GetFooA", "Hooked"]);
+        assert_eq!(names(&project.forwarded_exports), ["GetFooW", "SetFoo"]);
+
+        // A new filter re-decides every export, including the ones forwarded before
+        project.apply_filter(&filter(&[], &["GetFooA"]));
+        assert_eq!(
+            names(&project.exports),
+            ["DllMain", "GetFooW", "Hooked", "SetFoo"]
+        );
+        assert_eq!(names(&project.forwarded_exports), ["GetFooA"]);
+
+        // And an empty one leaves them as they are
+        project.apply_filter(&ExportFilter::default());
+        assert_eq!(names(&project.forwarded_exports), ["GetFooA"]);
+    }
+}
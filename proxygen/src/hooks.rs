@@ -0,0 +1,396 @@
+//! Finds a project's hooks, by walking its module tree from `src/lib.rs`.
+//!
+//! Hooks can live in `src/intercepted_exports.rs` or in any module reachable from `lib.rs`, eg. a `src/hooks/` tree
+//! declared with `mod hooks;`. Only functions carrying one of the proxygen macros count.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use quote::ToTokens;
+
+use crate::{Error, Result};
+
+/// The attributes that turn a function into an interception of an export
+const HOOK_ATTRIBUTES: &[&str] = &["forward", "proxy", "pre_hook", "post_hook"];
+
+/// A function that intercepts one of the original DLL's exports
+#[derive(Clone, Debug)]
+pub struct Hook {
+    /// The intercepted export's original name: the hook's `#[export_name]`, or the function's own name without one
+    pub export_name: String,
+    /// The file the hook is in, relative to the project
+    pub path: PathBuf,
+    pub line: usize,
+    /// The `#[cfg(...)]` attributes of the hook and the modules it's in, eg. for hooks that only exist on one arch
+    pub cfg: Vec<String>,
+}
+
+/// Every hook in the project at `project_dir`.
+///
+/// The generated `src/proxied_exports.rs` is skipped, since its forwarders aren't the user's hooks.
+/// Projects without a `src/lib.rs` only have their `src/intercepted_exports.rs` searched.
+pub fn find_hooks(project_dir: &Path) -> Result<Vec<Hook>> {
+    let src = project_dir.join("src");
+    let mut finder = HookFinder::new(project_dir, src.join("proxied_exports.rs"));
+    let lib = src.join("lib.rs");
+    if lib.is_file() {
+        finder.visit_file(&lib, &src, &[])?;
+    } else {
+        let intercepted_exports = src.join("intercepted_exports.rs");
+        if intercepted_exports.is_file() {
+            finder.visit_file(&intercepted_exports, &src.join("intercepted_exports"), &[])?;
+        }
+    }
+    Ok(finder.hooks)
+}
+
+/// The generated `#[forward]` thunks in the project's `src/proxied_exports.rs`
+pub fn find_forwarders(project_dir: &Path) -> Result<Vec<Hook>> {
+    let src = project_dir.join("src");
+    let mut finder = HookFinder::new(project_dir, PathBuf::new());
+    let proxied_exports = src.join("proxied_exports.rs");
+    if proxied_exports.is_file() {
+        finder.visit_file(&proxied_exports, &src.join("proxied_exports"), &[])?;
+    }
+    Ok(finder.hooks)
+}
+
+/// The groups of hooks that intercept the same export under the same `#[cfg(...)]`s, which would fail to link
+pub fn duplicate_hooks(hooks: &[Hook]) -> Vec<Vec<&Hook>> {
+    let mut by_export: BTreeMap<(&str, &[String]), Vec<&Hook>> = BTreeMap::new();
+    for hook in hooks.iter() {
+        by_export
+            .entry((&hook.export_name, &hook.cfg))
+            .or_default()
+            .push(hook);
+    }
+    by_export
+        .into_values()
+        .filter(|hooks| hooks.len() > 1)
+        .collect()
+}
+
+/// The original names of the exports the project's hooks intercept.
+///
+/// Fails with [`Error::DuplicateHooks`] if an export is hooked more than once under the same `#[cfg(...)]`s,
+/// which would fail to link anyway.
+pub fn find_intercepted_exports(project_dir: &Path) -> Result<HashSet<String>> {
+    let hooks = find_hooks(project_dir)?;
+    let duplicates: Vec<String> = duplicate_hooks(&hooks)
+        .iter()
+        .map(|hooks| {
+            let locations: Vec<String> = hooks
+                .iter()
+                .map(|hook| format!("{}:{}", hook.path.to_string_lossy(), hook.line))
+                .collect();
+            format!("'{}' in {}", hooks[0].export_name, locations.join(", "))
+        })
+        .collect();
+    if !duplicates.is_empty() {
+        return Err(Error::DuplicateHooks(duplicates));
+    }
+    Ok(hooks.into_iter().map(|hook| hook.export_name).collect())
+}
+
+struct HookFinder {
+    project_dir: PathBuf,
+    skipped: PathBuf,
+    visited: HashSet<PathBuf>,
+    hooks: Vec<Hook>,
+}
+
+impl HookFinder {
+    fn new(project_dir: &Path, skipped: PathBuf) -> Self {
+        Self {
+            project_dir: project_dir.to_path_buf(),
+            skipped,
+            visited: HashSet::new(),
+            hooks: Vec::new(),
+        }
+    }
+
+    /// Searches the file at `path`, whose child modules live in `module_dir`.
+    /// `cfg` holds the `#[cfg(...)]`s of the modules the file is in
+    fn visit_file(&mut self, path: &Path, module_dir: &Path, cfg: &[String]) -> Result<()> {
+        if *path == self.skipped || !self.visited.insert(path.to_path_buf()) {
+            return Ok(());
+        }
+        let file = syn::parse_file(&std::fs::read_to_string(path)?).map_err(|err| {
+            Error::InvalidHooks {
+                path: path.to_path_buf(),
+                message: format!("{} (line {})", err, err.span().start().line),
+            }
+        })?;
+        self.visit_items(&file.items, path, module_dir, cfg)
+    }
+
+    fn visit_items(
+        &mut self,
+        items: &[syn::Item],
+        path: &Path,
+        module_dir: &Path,
+        cfg: &[String],
+    ) -> Result<()> {
+        for item in items {
+            match item {
+                syn::Item::Fn(function) if function.attrs.iter().any(is_hook_attribute) => {
+                    self.hooks.push(Hook {
+                        export_name: hooked_export_name(function),
+                        path: path
+                            .strip_prefix(&self.project_dir)
+                            .unwrap_or(path)
+                            .to_path_buf(),
+                        line: function.sig.ident.span().start().line,
+                        cfg: cfg_attributes(cfg, &function.attrs),
+                    });
+                }
+                syn::Item::Mod(module) => {
+                    let name = module.ident.to_string();
+                    let cfg = cfg_attributes(cfg, &module.attrs);
+                    match module.content.as_ref() {
+                        Some((_, items)) => {
+                            self.visit_items(items, path, &module_dir.join(&name), &cfg)?
+                        }
+                        None => {
+                            if let Some((file, dir)) = module_file(module, module_dir) {
+                                self.visit_file(&file, &dir, &cfg)?;
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The file a `mod name;` declaration loads, and the folder its own child modules live in.
+/// Modules whose file doesn't exist are skipped, since they won't build anyway
+fn module_file(module: &syn::ItemMod, module_dir: &Path) -> Option<(PathBuf, PathBuf)> {
+    let name = module.ident.to_string();
+    if let Some(path) = path_attribute(&module.attrs) {
+        let file = module_dir.join(path);
+        let dir = file.with_extension("");
+        return file.is_file().then_some((file, dir));
+    }
+    let file = module_dir.join(format!("{}.rs", name));
+    if file.is_file() {
+        return Some((file, module_dir.join(name)));
+    }
+    let file = module_dir.join(&name).join("mod.rs");
+    file.is_file().then(|| (file, module_dir.join(name)))
+}
+
+fn path_attribute(attrs: &[syn::Attribute]) -> Option<String> {
+    attrs.iter().find_map(|attr| match &attr.meta {
+        syn::Meta::NameValue(syn::MetaNameValue {
+            path,
+            value:
+                syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(value),
+                    ..
+                }),
+            ..
+        }) if path.is_ident("path") => Some(value.value()),
+        _ => None,
+    })
+}
+
+fn hooked_export_name(function: &syn::ItemFn) -> String {
+    function
+        .attrs
+        .iter()
+        .find_map(|attr| match &attr.meta {
+            syn::Meta::NameValue(syn::MetaNameValue {
+                path,
+                value:
+                    syn::Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Str(export_name),
+                        ..
+                    }),
+                ..
+            }) if path.is_ident("export_name") => Some(export_name.value()),
+            _ => None,
+        })
+        .unwrap_or_else(|| function.sig.ident.to_string())
+}
+
+/// The `#[cfg(...)]`s inherited from the enclosing modules, plus the item's own
+fn cfg_attributes(inherited: &[String], attrs: &[syn::Attribute]) -> Vec<String> {
+    let mut cfg: Vec<String> = attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("cfg"))
+        .map(|attr| attr.meta.to_token_stream().to_string())
+        .chain(inherited.iter().cloned())
+        .collect();
+    cfg.sort();
+    cfg
+}
+
+/// Whether the attribute is one of [`HOOK_ATTRIBUTES`], by name or by path (eg. `proxygen_macros::proxy`)
+fn is_hook_attribute(attribute: &syn::Attribute) -> bool {
+    attribute
+        .path()
+        .segments
+        .last()
+        .is_some_and(|segment| HOOK_ATTRIBUTES.contains(&segment.ident.to_string().as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    /// The hooks found, as `export path:line [cfg]`, sorted
+    fn describe(hooks: &[Hook]) -> Vec<String> {
+        let mut described: Vec<String> = hooks
+            .iter()
+            .map(|hook| {
+                format!(
+                    "{} {}:{} {:?}",
+                    hook.export_name,
+                    hook.path.to_string_lossy().replace('\\', "/"),
+                    hook.line,
+                    hook.cfg
+                )
+            })
+            .collect();
+        described.sort();
+        described
+    }
+
+    #[test]
+    fn walks_module_tree_from_lib() {
+        let project = TempDir::new("walks_module_tree_from_lib");
+        project.write(
+            "src/lib.rs",
+            "mod intercepted_exports;\nmod proxied_exports;\nmod hooks;\nmod missing;\n",
+        );
+        project.write(
+            "src/intercepted_exports.rs",
+            "#[pre_hook(sig = \"unknown\")]\n#[export_name = \"Foo\"]\npub extern \"C\" fn foo_hook() {}\n",
+        );
+        // The generated forwarders aren't hooks
+        project.write(
+            "src/proxied_exports.rs",
+            "#[forward]\n#[export_name = \"Bar\"]\npub extern \"C\" fn Bar() {}\n",
+        );
+        project.write(
+            "src/hooks/mod.rs",
+            "mod file;\n#[path = \"elsewhere.rs\"]\nmod renamed;\nmod inline {\n    #[proxygen_macros::proxy(sig = \"unknown\")]\n    pub extern \"C\" fn Inline() {}\n}\n",
+        );
+        project.write(
+            "src/hooks/file.rs",
+            "fn helper() {}\n\n#[post_hook(sig = \"unknown\")]\npub extern \"C\" fn FromFile() {}\n",
+        );
+        project.write(
+            "src/hooks/elsewhere.rs",
+            "#[forward]\npub extern \"C\" fn Elsewhere() {}\n",
+        );
+
+        assert_eq!(
+            describe(&find_hooks(project.path()).unwrap()),
+            [
+                "Elsewhere src/hooks/elsewhere.rs:2 []",
+                "Foo src/intercepted_exports.rs:3 []",
+                "FromFile src/hooks/file.rs:4 []",
+                "Inline src/hooks/mod.rs:6 []",
+            ]
+        );
+        assert_eq!(
+            describe(&find_forwarders(project.path()).unwrap()),
+            ["Bar src/proxied_exports.rs:3 []"]
+        );
+    }
+
+    #[test]
+    fn collects_cfg_of_enclosing_modules() {
+        let project = TempDir::new("collects_cfg_of_enclosing_modules");
+        project.write(
+            "src/lib.rs",
+            "#[cfg(target_arch = \"x86\")]\nmod x86;\nmod intercepted_exports;\n",
+        );
+        project.write(
+            "src/x86.rs",
+            "#[cfg(feature = \"a\")]\nmod nested {\n    #[pre_hook(sig = \"unknown\")]\n    pub extern \"C\" fn Foo() {}\n}\n",
+        );
+        project.write(
+            "src/intercepted_exports.rs",
+            "#[cfg(target_arch = \"x86_64\")]\n#[pre_hook(sig = \"unknown\")]\npub extern \"C\" fn Foo() {}\n",
+        );
+
+        let hooks = find_hooks(project.path()).unwrap();
+        assert_eq!(
+            describe(&hooks),
+            [
+                "Foo src/intercepted_exports.rs:3 [\"cfg (target_arch = \\\"x86_64\\\")\"]",
+                "Foo src/x86.rs:4 [\"cfg (feature = \\\"a\\\")\", \"cfg (target_arch = \\\"x86\\\")\"]",
+            ]
+        );
+        // Hooks behind different cfgs don't clash
+        assert!(duplicate_hooks(&hooks).is_empty());
+        assert_eq!(
+            find_intercepted_exports(project.path()).unwrap(),
+            HashSet::from(["Foo".to_string()])
+        );
+    }
+
+    #[test]
+    fn rejects_duplicate_hooks() {
+        let project = TempDir::new("rejects_duplicate_hooks");
+        project.write("src/lib.rs", "mod intercepted_exports;\nmod other;\n");
+        project.write(
+            "src/intercepted_exports.rs",
+            "#[pre_hook(sig = \"unknown\")]\npub extern \"C\" fn Foo() {}\n",
+        );
+        project.write(
+            "src/other.rs",
+            "#[proxy(sig = \"unknown\")]\n#[export_name = \"Foo\"]\npub extern \"C\" fn other_foo() {}\n",
+        );
+
+        let hooks = find_hooks(project.path()).unwrap();
+        assert_eq!(duplicate_hooks(&hooks).len(), 1);
+        match find_intercepted_exports(project.path()) {
+            Err(Error::DuplicateHooks(duplicates)) => assert_eq!(
+                duplicates,
+                ["'Foo' in src/intercepted_exports.rs:2, src/other.rs:3"]
+            ),
+            result => panic!("expected duplicate hooks, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn searches_intercepted_exports_without_lib() {
+        let project = TempDir::new("searches_intercepted_exports_without_lib");
+        project.write(
+            "src/intercepted_exports.rs",
+            "mod nested;\n#[pre_hook(sig = \"unknown\")]\npub extern \"C\" fn Foo() {}\n",
+        );
+        project.write(
+            "src/intercepted_exports/nested.rs",
+            "#[pre_hook(sig = \"unknown\")]\npub extern \"C\" fn Bar() {}\n",
+        );
+
+        assert_eq!(
+            describe(&find_hooks(project.path()).unwrap()),
+            [
+                "Bar src/intercepted_exports/nested.rs:2 []",
+                "Foo src/intercepted_exports.rs:3 []",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_unparseable_files() {
+        let project = TempDir::new("reports_unparseable_files");
+        project.write("src/lib.rs", "mod intercepted_exports;\n");
+        project.write("src/intercepted_exports.rs", "\nfn broken( {}\n");
+        assert!(matches!(
+            find_hooks(project.path()),
+            Err(Error::InvalidHooks { .. })
+        ));
+    }
+}
@@ -0,0 +1,354 @@
+use exe::Arch;
+use object::{
+    coff::ImportFile,
+    pe::{self, ImportObjectHeader},
+    read::archive::ArchiveFile,
+    Architecture, FileKind, Object, ObjectSection, ObjectSymbol, SectionKind,
+};
+
+use crate::{exports::ExportKind, Error, Result};
+
+/// How an import library entry maps its public symbol onto the DLL's export name
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportNameType {
+    /// Imported by ordinal only, the DLL doesn't export a name for it
+    Ordinal,
+    /// The export name is the symbol name
+    Name,
+    /// The export name is the symbol name without its leading `?`, `@` or `_`
+    NameNoPrefix,
+    /// The export name is the symbol name without its prefix and anything after the first `@`
+    NameUndecorate,
+    /// The export name is stored explicitly in the import object
+    NameExportAs,
+}
+
+/// A single export recovered from an import library
+#[derive(Clone, Debug)]
+pub struct ImportEntry {
+    /// The public symbol name that code links against (eg. `_Foo@12`)
+    pub symbol: String,
+    /// The name exported from the DLL, if it is imported by name
+    pub export_name: Option<String>,
+    /// The ordinal for ordinal imports, otherwise the hint
+    pub ordinal_or_hint: u16,
+    pub name_type: ImportNameType,
+    pub kind: ExportKind,
+}
+
+/// A COFF archive import library, either an MSVC style `.lib` made up of short import objects,
+/// or a MinGW `.dll.a` made up of regular COFF objects
+pub struct ImportLibrary {
+    pub dll_name: String,
+    pub arch: Arch,
+    pub entries: Vec<ImportEntry>,
+}
+
+impl ImportLibrary {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let archive = ArchiveFile::parse(data)?;
+        let mut dll_name: Option<String> = None;
+        let mut arch: Option<Arch> = None;
+        let mut entries: Vec<ImportEntry> = Vec::new();
+
+        for member in archive.members() {
+            let member_data = member?.data(data)?;
+            match FileKind::parse(member_data) {
+                Ok(FileKind::CoffImport) => {
+                    let (member_dll, member_arch, entry) = parse_short_import(member_data)?;
+                    dll_name.get_or_insert(member_dll);
+                    arch.get_or_insert(member_arch);
+                    entries.push(entry);
+                }
+                Ok(FileKind::Coff) => {
+                    let file = object::File::parse(member_data)?;
+                    if let Some(member_arch) = to_arch(file.architecture()) {
+                        arch.get_or_insert(member_arch);
+                    }
+                    if let Some(member_dll) = parse_mingw_dll_name(&file)? {
+                        dll_name.get_or_insert(member_dll);
+                    }
+                    if let Some(entry) = parse_mingw_import(&file)? {
+                        entries.push(entry);
+                    }
+                }
+                // Symbol tables, long name tables and anything else we don't understand
+                _ => {}
+            }
+        }
+
+        if entries.is_empty() {
+            return Err(malformed(
+                "Archive doesn't contain any import objects. Is it a static library?",
+            ));
+        }
+        Ok(Self {
+            dll_name: dll_name
+                .ok_or_else(|| malformed("Failed to find DLL name"))?,
+            arch: arch.ok_or_else(|| {
+                Error::UnsupportedArch("No x86 or x64 objects in import library".into())
+            })?,
+            entries,
+        })
+    }
+}
+
+fn malformed(message: impl Into<String>) -> Error {
+    Error::Malformed {
+        format: "import library",
+        message: message.into(),
+    }
+}
+
+fn to_arch(architecture: Architecture) -> Option<Arch> {
+    match architecture {
+        Architecture::I386 => Some(Arch::X86),
+        Architecture::X86_64 => Some(Arch::X64),
+        _ => None,
+    }
+}
+
+/// Strips the leading C decoration from x86 symbols, since it isn't part of the exported name
+fn undecorate_c_symbol(symbol: &str, arch: Arch) -> &str {
+    match arch {
+        Arch::X86 => symbol.strip_prefix('_').unwrap_or(symbol),
+        Arch::X64 => symbol,
+    }
+}
+
+/// Parses a short import object, as produced by MSVC's `lib.exe` and `llvm-dlltool`
+fn parse_short_import(data: &[u8]) -> Result<(String, Arch, ImportEntry)> {
+    let import = ImportFile::parse(data)?;
+    let header = ImportObjectHeader::parse(data, &mut 0)?;
+    let arch = to_arch(import.architecture()).ok_or_else(|| {
+        Error::UnsupportedArch(format!(
+            "Import library machine type {:#06x}",
+            header.machine.get(object::LittleEndian)
+        ))
+    })?;
+    let symbol = String::from_utf8_lossy(import.symbol()).into_owned();
+    let name_type = match header.name_type() {
+        pe::IMPORT_OBJECT_ORDINAL => ImportNameType::Ordinal,
+        pe::IMPORT_OBJECT_NAME => ImportNameType::Name,
+        pe::IMPORT_OBJECT_NAME_NO_PREFIX => ImportNameType::NameNoPrefix,
+        pe::IMPORT_OBJECT_NAME_UNDECORATE => ImportNameType::NameUndecorate,
+        _ => ImportNameType::NameExportAs,
+    };
+    let (export_name, ordinal_or_hint) = match import.import() {
+        object::coff::ImportName::Ordinal(ordinal) => (None, ordinal),
+        object::coff::ImportName::Name(name) => (
+            Some(String::from_utf8_lossy(name).into_owned()),
+            header.ordinal_or_hint.get(object::LittleEndian),
+        ),
+    };
+    let kind = match import.import_type() {
+        object::coff::ImportType::Code => ExportKind::Code,
+        _ => ExportKind::Data,
+    };
+
+    Ok((
+        String::from_utf8_lossy(import.dll()).into_owned(),
+        arch,
+        ImportEntry {
+            symbol,
+            export_name,
+            ordinal_or_hint,
+            name_type,
+            kind,
+        },
+    ))
+}
+
+/// Looks for the DLL name in a MinGW import library.
+///
+/// binutils' `dlltool` puts it in the `.idata$7` section of the tail object, under a `*_iname` symbol
+fn parse_mingw_dll_name(file: &object::File) -> Result<Option<String>> {
+    for symbol in file.symbols() {
+        if !symbol.name()?.ends_with("_iname") {
+            continue;
+        }
+        if let Some(section_index) = symbol.section_index() {
+            let section_data = file.section_by_index(section_index)?.data()?;
+            let name: Vec<u8> = section_data
+                .iter()
+                .skip(symbol.address() as usize)
+                .take_while(|c| **c != 0)
+                .copied()
+                .collect();
+            if !name.is_empty() {
+                return Ok(Some(String::from_utf8_lossy(&name).into_owned()));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Parses one export's member object of a MinGW import library, as produced by binutils' `dlltool`
+///
+/// Each export gets an `__imp_` symbol in `.idata$5`. The lookup table entry in `.idata$4` either holds
+/// the ordinal flag, or gets relocated to the hint/name entry in `.idata$6`.
+/// Code exports additionally get a jump thunk in `.text` under the plain symbol name.
+fn parse_mingw_import(file: &object::File) -> Result<Option<ImportEntry>> {
+    let Some(arch) = to_arch(file.architecture()) else {
+        return Ok(None);
+    };
+    let Some(imp_symbol) = file.symbols().find(|s| {
+        s.is_definition() && s.name().is_ok_and(|name| name.starts_with("__imp_"))
+    }) else {
+        return Ok(None);
+    };
+    let symbol = imp_symbol.name()?["__imp_".len()..].to_string();
+
+    let is_code = file.symbols().any(|s| {
+        s.is_definition()
+            && s.name().is_ok_and(|name| name == symbol)
+            && s
+                .section_index()
+                .and_then(|i| file.section_by_index(i).ok())
+                .is_some_and(|section| section.kind() == SectionKind::Text)
+    });
+    let kind = if is_code {
+        ExportKind::Code
+    } else {
+        ExportKind::Data
+    };
+
+    if let Some(hint_name) = file.section_by_name(".idata$6") {
+        let hint_name = hint_name.data()?;
+        if hint_name.len() < 2 {
+            return Err(malformed(format!(
+                "Truncated hint/name entry for {}",
+                symbol
+            )));
+        }
+        let hint = u16::from_le_bytes([hint_name[0], hint_name[1]]);
+        let name: Vec<u8> = hint_name[2..]
+            .iter()
+            .take_while(|c| **c != 0)
+            .copied()
+            .collect();
+        let export_name = String::from_utf8_lossy(&name).into_owned();
+        let undecorated = undecorate_c_symbol(&symbol, arch);
+        let name_type = if export_name == symbol {
+            ImportNameType::Name
+        } else if export_name == undecorated {
+            ImportNameType::NameNoPrefix
+        } else if undecorated.split('@').next() == Some(export_name.as_str()) {
+            ImportNameType::NameUndecorate
+        } else {
+            ImportNameType::NameExportAs
+        };
+        return Ok(Some(ImportEntry {
+            symbol,
+            export_name: Some(export_name),
+            ordinal_or_hint: hint,
+            name_type,
+            kind,
+        }));
+    }
+
+    // No hint/name entry, so this must be imported by ordinal
+    let lookup = file
+        .section_by_name(".idata$4")
+        .ok_or_else(|| malformed(format!("Missing lookup table entry for {}", symbol)))?
+        .data()?;
+    if lookup.len() < 2 {
+        return Err(malformed(format!(
+            "Truncated lookup table entry for {}",
+            symbol
+        )));
+    }
+    Ok(Some(ImportEntry {
+        symbol,
+        export_name: None,
+        ordinal_or_hint: u16::from_le_bytes([lookup[0], lookup[1]]),
+        name_type: ImportNameType::Ordinal,
+        kind,
+    }))
+}
+
+impl ImportEntry {
+    /// The name the proxy should use for this export.
+    ///
+    /// Ordinal imports don't have an exported name, so we fall back to the undecorated symbol name
+    pub fn proxy_name(&self, arch: Arch) -> &str {
+        match &self.export_name {
+            Some(name) => name,
+            None => undecorate_c_symbol(&self.symbol, arch),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::fixture;
+
+    fn entry<'a>(library: &'a ImportLibrary, symbol: &str) -> &'a ImportEntry {
+        library
+            .entries
+            .iter()
+            .find(|entry| entry.symbol == symbol)
+            .unwrap_or_else(|| panic!("no entry for {}", symbol))
+    }
+
+    #[test]
+    fn parses_short_import_library() {
+        let library = ImportLibrary::parse(&fixture("foo.lib")).unwrap();
+        assert_eq!(library.dll_name, "foo.dll");
+        assert_eq!(library.arch, Arch::X64);
+        assert_eq!(library.entries.len(), 5);
+
+        let foo = entry(&library, "Foo");
+        assert_eq!(foo.export_name.as_deref(), Some("Foo"));
+        assert_eq!(foo.name_type, ImportNameType::Name);
+        assert_eq!(foo.kind, ExportKind::Code);
+
+        let data = entry(&library, "SomeData");
+        assert_eq!(data.kind, ExportKind::Data);
+
+        let hidden = entry(&library, "Hidden");
+        assert_eq!(hidden.export_name, None);
+        assert_eq!(hidden.name_type, ImportNameType::Ordinal);
+        assert_eq!(hidden.ordinal_or_hint, 7);
+        assert_eq!(hidden.proxy_name(library.arch), "Hidden");
+
+        // x64 doesn't decorate C symbols, so the name is exported as is
+        assert_eq!(entry(&library, "_Under").proxy_name(library.arch), "_Under");
+    }
+
+    #[test]
+    fn parses_mingw_import_library() {
+        let library = ImportLibrary::parse(&fixture("libfoo.dll.a")).unwrap();
+        assert_eq!(library.dll_name, "foo.dll");
+        assert_eq!(library.arch, Arch::X64);
+        assert_eq!(library.entries.len(), 2);
+
+        let foo = entry(&library, "Foo");
+        assert_eq!(foo.export_name.as_deref(), Some("Foo"));
+        assert_eq!(foo.name_type, ImportNameType::Name);
+        assert_eq!(foo.kind, ExportKind::Code);
+
+        let hidden = entry(&library, "Hidden");
+        assert_eq!(hidden.export_name, None);
+        assert_eq!(hidden.name_type, ImportNameType::Ordinal);
+        assert_eq!(hidden.ordinal_or_hint, 7);
+        assert_eq!(hidden.kind, ExportKind::Data);
+    }
+
+    #[test]
+    fn rejects_archive_without_imports() {
+        let archive = b"!<arch>\n";
+        assert!(matches!(
+            ImportLibrary::parse(archive),
+            Err(Error::Malformed { .. })
+        ));
+    }
+
+    #[test]
+    fn undecorates_x86_symbols_only() {
+        assert_eq!(undecorate_c_symbol("_Foo", Arch::X86), "Foo");
+        assert_eq!(undecorate_c_symbol("Foo", Arch::X86), "Foo");
+        assert_eq!(undecorate_c_symbol("_Foo", Arch::X64), "_Foo");
+    }
+}
@@ -0,0 +1,56 @@
+//! A DLL export dumper and proxy generator.
+//!
+//! This is the library behind the `proxygen` command line tool, for driving proxy generation from your own tooling.
+//!
+//! - [`DLLFile`] reads exports from DLLs, import libraries, ELF shared objects and Mach-O dylibs
+//! - [`ProxyProject`] models a proxy project, and generates its files in memory as [`ProjectFiles`]
+//! - [`create_proxy_project`] and [`update_proxy_project`] do the same as `proxygen generate` and `proxygen merge`
+//! - [`create_proxy_workspace`] does the same as `proxygen generate --workspace`
+//! - [`hooks::find_hooks`] finds a project's hooks, wherever they are in its module tree
+//!
+//! ```no_run
+//! use proxygen::{DLLFile, ProxyProject, ProxyTemplates};
+//!
+//! # fn main() -> proxygen::Result<()> {
+//! let dll = DLLFile::new("version.dll")?;
+//! let mut project = ProxyProject::new(
+//!     "version_proxy",
+//!     dll.get_dll_name()?,
+//!     dll.get_arch()?,
+//!     dll.parse_exports()?,
+//! );
+//! project.exclude_unprovidable_exports(true)?;
+//! for (path, contents) in project.generate(&ProxyTemplates::new()?)?.iter() {
+//!     println!("{}: {} bytes", path.display(), contents.len());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod audit;
+pub mod check;
+mod elf;
+mod error;
+pub mod exports;
+pub mod filter;
+pub mod hooks;
+mod implib;
+mod macho;
+mod manifest;
+pub mod project;
+pub mod proxy;
+pub mod scan;
+#[cfg(test)]
+mod test_support;
+
+pub use error::{Error, Result};
+pub use exe::Arch;
+pub use exports::{DLLFile, ExportKind, ExportName, UnprovidableExport, UnprovidableReason};
+pub use filter::{ExportFilter, ExportPattern};
+pub use manifest::MANIFEST_FILE;
+pub use project::{ArchExports, FileChange, LoadingStrategy, ProjectFiles, ProxyProject};
+pub use proxy::{
+    create_proxy_project, create_proxy_workspace, update_proxy_project, upgrade_proxy_project,
+    GenerationOptions, GenerationReport, ProxyTemplates, ScaffoldMode, ScaffoldOptions,
+    UpgradeReport, Vcs, WorkspaceReport,
+};
@@ -0,0 +1,428 @@
+use object::{
+    macho,
+    read::macho::{
+        FatArch, LoadCommandVariant, MachHeader, MachOFatFile32, MachOFatFile64, Nlist, Segment,
+    },
+    Architecture, Endianness, FileKind, Object,
+};
+
+use crate::{exports::ExportKind, Error, Result};
+
+/// A symbol exported from a Mach-O dylib
+#[derive(Clone, Debug)]
+pub struct MachOExport {
+    /// The symbol name, including its leading `_`
+    pub name: String,
+    pub kind: ExportKind,
+    /// Whether this is a weak definition, which may be coalesced with another image's definition
+    pub weak: bool,
+    /// For re-exports, the dylib the symbol really lives in and its name there
+    pub reexport: Option<(String, String)>,
+}
+
+/// A single architecture of a Mach-O dylib
+pub struct MachOSlice {
+    pub architecture: Architecture,
+    /// The dylib's install name, from `LC_ID_DYLIB`
+    pub install_name: Option<String>,
+    /// Dylibs whose exports are all re-exported (`LC_REEXPORT_DYLIB`)
+    pub reexported_dylibs: Vec<String>,
+    pub exports: Vec<MachOExport>,
+}
+
+/// A Mach-O dylib, either thin or a fat (universal) binary with one slice per architecture
+pub struct MachOLibrary {
+    pub slices: Vec<MachOSlice>,
+}
+
+impl MachOLibrary {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let slices = match FileKind::parse(data)? {
+            FileKind::MachOFat32 => MachOFatFile32::parse(data)?
+                .arches()
+                .iter()
+                .map(|arch| MachOSlice::parse(arch.data(data)?))
+                .collect::<Result<Vec<_>>>()?,
+            FileKind::MachOFat64 => MachOFatFile64::parse(data)?
+                .arches()
+                .iter()
+                .map(|arch| MachOSlice::parse(arch.data(data)?))
+                .collect::<Result<Vec<_>>>()?,
+            _ => vec![MachOSlice::parse(data)?],
+        };
+        Ok(Self { slices })
+    }
+}
+
+impl MachOSlice {
+    fn parse(data: &[u8]) -> Result<Self> {
+        let architecture = object::File::parse(data)?.architecture();
+        match FileKind::parse(data)? {
+            FileKind::MachO32 => parse_slice::<macho::MachHeader32<Endianness>>(data, architecture),
+            FileKind::MachO64 => parse_slice::<macho::MachHeader64<Endianness>>(data, architecture),
+            _ => Err(malformed("Not a Mach-O file")),
+        }
+    }
+}
+
+/// A segment's address range and whether it's executable, for telling code and data exports apart
+struct SegmentRange {
+    start: u64,
+    end: u64,
+    executable: bool,
+    /// Whether the segment starts at the beginning of the file, ie. contains the Mach-O header
+    maps_header: bool,
+}
+
+fn parse_slice<Mach: MachHeader<Endian = Endianness>>(
+    data: &[u8],
+    architecture: Architecture,
+) -> Result<MachOSlice> {
+    let header = Mach::parse(data, 0)?;
+    let endian = header.endian()?;
+
+    let mut install_name = None;
+    let mut dylibs = Vec::new();
+    let mut reexported_dylibs = Vec::new();
+    let mut segments = Vec::new();
+    let mut export_trie: Option<(u32, u32)> = None;
+    let mut symtab = None;
+    let mut commands = header.load_commands(endian, data, 0)?;
+    while let Some(command) = commands.next()? {
+        if let Some((segment, _)) = Mach::Segment::from_command(command)? {
+            let start: u64 = segment.vmaddr(endian).into();
+            segments.push(SegmentRange {
+                start,
+                end: start
+                    .checked_add(segment.vmsize(endian).into())
+                    .ok_or_else(|| {
+                        malformed("Segment extends past the end of the address space")
+                    })?,
+                executable: segment.initprot(endian) & macho::VM_PROT_EXECUTE != 0,
+                maps_header: segment.fileoff(endian).into() == 0
+                    && segment.filesize(endian).into() != 0,
+            });
+            continue;
+        }
+        match command.variant()? {
+            LoadCommandVariant::IdDylib(dylib) => {
+                install_name = Some(
+                    String::from_utf8_lossy(command.string(endian, dylib.dylib.name)?).into_owned(),
+                );
+            }
+            // Ordinals in the export trie count every dylib load command, in order
+            LoadCommandVariant::Dylib(dylib) => {
+                let name =
+                    String::from_utf8_lossy(command.string(endian, dylib.dylib.name)?).into_owned();
+                if command.cmd() == macho::LC_REEXPORT_DYLIB {
+                    reexported_dylibs.push(name.clone());
+                }
+                dylibs.push(name);
+            }
+            LoadCommandVariant::DyldInfo(dyld_info) => {
+                export_trie.get_or_insert((
+                    dyld_info.export_off.get(endian),
+                    dyld_info.export_size.get(endian),
+                ));
+            }
+            LoadCommandVariant::LinkeditData(linkedit)
+                if command.cmd() == macho::LC_DYLD_EXPORTS_TRIE =>
+            {
+                export_trie = Some((linkedit.dataoff.get(endian), linkedit.datasize.get(endian)));
+            }
+            LoadCommandVariant::Symtab(command) => symtab = Some(command),
+            _ => {}
+        }
+    }
+
+    let image_base = segments
+        .iter()
+        .find(|segment| segment.maps_header)
+        .map(|segment| segment.start)
+        .unwrap_or_default();
+    let kind_at = |address: u64| {
+        let executable = segments
+            .iter()
+            .any(|segment| segment.executable && (segment.start..segment.end).contains(&address));
+        if executable {
+            ExportKind::Code
+        } else {
+            ExportKind::Data
+        }
+    };
+
+    let exports = match export_trie {
+        Some((offset, size)) if size != 0 => {
+            let trie = data
+                .get(offset as usize..offset as usize + size as usize)
+                .ok_or_else(|| malformed("Export trie is out of bounds"))?;
+            parse_export_trie(trie)?
+                .into_iter()
+                .map(|entry| {
+                    let kind = match &entry.value {
+                        TrieValue::Address(address)
+                            if entry.flags & macho::EXPORT_SYMBOL_FLAGS_KIND_MASK
+                                == macho::EXPORT_SYMBOL_FLAGS_KIND_REGULAR =>
+                        {
+                            kind_at(image_base.checked_add(*address).ok_or_else(|| {
+                                malformed(format!("Address of {} is out of range", entry.name))
+                            })?)
+                        }
+                        // Resolvers pick the implementation of a function at load time
+                        TrieValue::StubAndResolver => ExportKind::Code,
+                        // Re-exports are assumed to be functions, like the PE forwarders they resemble
+                        TrieValue::Reexport { .. } => ExportKind::Code,
+                        _ => ExportKind::Data,
+                    };
+                    let reexport = match entry.value {
+                        TrieValue::Reexport {
+                            ordinal,
+                            import_name,
+                        } => {
+                            let dylib = dylibs
+                                .get((ordinal as usize).wrapping_sub(1))
+                                .cloned()
+                                .ok_or_else(|| {
+                                    malformed(format!(
+                                        "Re-export of {} refers to unknown dylib ordinal {}",
+                                        entry.name, ordinal
+                                    ))
+                                })?;
+                            // An empty import name means it has the same name in the other dylib
+                            let import_name = if import_name.is_empty() {
+                                entry.name.clone()
+                            } else {
+                                import_name
+                            };
+                            Some((dylib, import_name))
+                        }
+                        _ => None,
+                    };
+                    Ok(MachOExport {
+                        name: entry.name,
+                        kind,
+                        weak: entry.flags & macho::EXPORT_SYMBOL_FLAGS_WEAK_DEFINITION != 0,
+                        reexport,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?
+        }
+        // Without dyld info, fall back to the external symbols in the symbol table
+        _ => match symtab {
+            Some(symtab) => {
+                let symbols = symtab.symbols::<Mach, _>(endian, data)?;
+                symbols
+                    .iter()
+                    .filter(|symbol| {
+                        !symbol.is_stab()
+                            && symbol.n_type() & macho::N_EXT != 0
+                            && symbol.n_type() & macho::N_TYPE == macho::N_SECT
+                    })
+                    .map(|symbol| {
+                        Ok(MachOExport {
+                            name: String::from_utf8_lossy(symbol.name(endian, symbols.strings())?)
+                                .into_owned(),
+                            kind: kind_at(symbol.n_value(endian).into()),
+                            weak: symbol.n_desc(endian) & macho::N_WEAK_DEF != 0,
+                            reexport: None,
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?
+            }
+            None => Vec::new(),
+        },
+    };
+
+    Ok(MachOSlice {
+        architecture,
+        install_name,
+        reexported_dylibs,
+        exports,
+    })
+}
+
+fn malformed(message: impl Into<String>) -> Error {
+    Error::Malformed {
+        format: "Mach-O file",
+        message: message.into(),
+    }
+}
+
+enum TrieValue {
+    /// Offset of the symbol from the image base
+    Address(u64),
+    StubAndResolver,
+    Reexport {
+        ordinal: u64,
+        import_name: String,
+    },
+}
+
+struct TrieEntry {
+    name: String,
+    flags: u32,
+    value: TrieValue,
+}
+
+/// Walks a dyld export trie, as found in `LC_DYLD_INFO` or `LC_DYLD_EXPORTS_TRIE`.
+///
+/// Each node has an optional terminal (export info), followed by edges labelled with the next part of the symbol name
+fn parse_export_trie(trie: &[u8]) -> Result<Vec<TrieEntry>> {
+    let mut entries = Vec::new();
+    let mut visited = vec![false; trie.len()];
+    let mut pending = vec![(0usize, Vec::new())];
+    while let Some((node, prefix)) = pending.pop() {
+        if node >= trie.len() || std::mem::replace(&mut visited[node], true) {
+            return Err(malformed("Malformed export trie"));
+        }
+        let mut offset = node;
+        let terminal_size = read_uleb128(trie, &mut offset)? as usize;
+        let children_offset = offset
+            .checked_add(terminal_size)
+            .ok_or_else(|| malformed("Malformed export trie"))?;
+        if terminal_size != 0 {
+            let flags = read_uleb128(trie, &mut offset)? as u32;
+            let value = if flags & macho::EXPORT_SYMBOL_FLAGS_REEXPORT != 0 {
+                let ordinal = read_uleb128(trie, &mut offset)?;
+                let import_name = read_cstring(trie, &mut offset)?;
+                TrieValue::Reexport {
+                    ordinal,
+                    import_name: String::from_utf8_lossy(import_name).into_owned(),
+                }
+            } else if flags & macho::EXPORT_SYMBOL_FLAGS_STUB_AND_RESOLVER != 0 {
+                TrieValue::StubAndResolver
+            } else {
+                TrieValue::Address(read_uleb128(trie, &mut offset)?)
+            };
+            entries.push(TrieEntry {
+                name: String::from_utf8_lossy(&prefix).into_owned(),
+                flags,
+                value,
+            });
+        }
+
+        let mut offset = children_offset;
+        let child_count = *trie
+            .get(offset)
+            .ok_or_else(|| malformed("Malformed export trie"))?;
+        offset += 1;
+        for _ in 0..child_count {
+            let edge = read_cstring(trie, &mut offset)?;
+            let child = read_uleb128(trie, &mut offset)? as usize;
+            pending.push((child, [prefix.as_slice(), edge].concat()));
+        }
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(entries)
+}
+
+fn read_uleb128(data: &[u8], offset: &mut usize) -> Result<u64> {
+    let mut value = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = *data
+            .get(*offset)
+            .ok_or_else(|| malformed("Truncated ULEB128 value in export trie"))?;
+        *offset += 1;
+        if shift < 64 {
+            value |= ((byte & 0x7f) as u64) << shift;
+        }
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+}
+
+fn read_cstring<'data>(data: &'data [u8], offset: &mut usize) -> Result<&'data [u8]> {
+    let rest = data
+        .get(*offset..)
+        .ok_or_else(|| malformed("Truncated string in export trie"))?;
+    let len = rest
+        .iter()
+        .position(|c| *c == 0)
+        .ok_or_else(|| malformed("Unterminated string in export trie"))?;
+    *offset += len + 1;
+    Ok(&rest[..len])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::fixture;
+
+    fn export<'a>(slice: &'a MachOSlice, name: &str) -> &'a MachOExport {
+        slice
+            .exports
+            .iter()
+            .find(|export| export.name == name)
+            .unwrap_or_else(|| panic!("no export {}", name))
+    }
+
+    #[test]
+    fn parses_every_slice_of_fat_dylib() {
+        let library = MachOLibrary::parse(&fixture("fixture.dylib")).unwrap();
+        let architectures: Vec<Architecture> = library
+            .slices
+            .iter()
+            .map(|slice| slice.architecture)
+            .collect();
+        assert_eq!(architectures, [Architecture::X86_64, Architecture::Aarch64]);
+        for slice in library.slices.iter() {
+            assert_eq!(
+                slice.install_name.as_deref(),
+                Some("/usr/lib/libfixture.dylib")
+            );
+            assert_eq!(slice.reexported_dylibs, ["/usr/lib/libother.dylib"]);
+        }
+        // Only the arm64 slice has its trie in `LC_DYLD_EXPORTS_TRIE`, and an extra export
+        assert_eq!(library.slices[0].exports.len(), 6);
+        assert_eq!(library.slices[1].exports.len(), 7);
+        export(&library.slices[1], "_arm64_only");
+    }
+
+    #[test]
+    fn reads_kinds_and_flags_from_export_trie() {
+        let library = MachOLibrary::parse(&fixture("fixture.dylib")).unwrap();
+        for slice in library.slices.iter() {
+            assert_eq!(export(slice, "_code").kind, ExportKind::Code);
+            assert_eq!(export(slice, "_data").kind, ExportKind::Data);
+            assert_eq!(export(slice, "_resolved").kind, ExportKind::Code);
+            assert!(export(slice, "_weak").weak);
+            assert!(!export(slice, "_code").weak);
+        }
+    }
+
+    #[test]
+    fn resolves_reexports_to_their_dylib() {
+        let library = MachOLibrary::parse(&fixture("fixture.dylib")).unwrap();
+        let slice = &library.slices[0];
+        assert_eq!(
+            export(slice, "_strdup_alias").reexport,
+            Some(("/usr/lib/libSystem.B.dylib".into(), "_strdup".into()))
+        );
+        // An empty import name means the same name
+        assert_eq!(
+            export(slice, "_same").reexport,
+            Some(("/usr/lib/libother.dylib".into(), "_same".into()))
+        );
+        assert_eq!(export(slice, "_code").reexport, None);
+    }
+
+    #[test]
+    fn rejects_malformed_export_tries() {
+        // A node whose only child is itself
+        let cyclic = [0, 1, b'a', 0, 0];
+        assert!(parse_export_trie(&cyclic).is_err());
+        // A terminal size so large that skipping over it overflows
+        let mut overflowing = vec![0xff; 9];
+        overflowing.extend([0x01, 0]);
+        assert!(matches!(
+            parse_export_trie(&overflowing),
+            Err(Error::Malformed { .. })
+        ));
+        // An edge pointing past the end
+        let truncated = [0, 1, b'a', 0, 0x40];
+        assert!(parse_export_trie(&truncated).is_err());
+    }
+}
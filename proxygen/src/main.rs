@@ -1,10 +1,14 @@
-mod exports;
-mod proxy;
-
-use crate::exports::DLLFile;
 use anyhow::Result;
 use clap::{Parser, Subcommand};
-use std::{collections::BTreeSet, path::PathBuf};
+use proxygen::{
+    audit, check, check::Issue, scan, ArchExports, DLLFile, ExportFilter, ExportKind, ExportName,
+    ExportPattern, GenerationOptions, GenerationReport, LoadingStrategy, ProxyTemplates,
+    ScaffoldMode, ScaffoldOptions, UpgradeReport, Vcs, MANIFEST_FILE,
+};
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
 
 /// A DLL export dumper and proxy generator
 #[derive(Parser)]
@@ -17,64 +21,798 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Prints out the exported functions from a given PE file
+    /// Prints out the exported functions from a given PE file, import library, ELF shared object or Mach-O dylib
     DumpExports {
-        /// Path to the DLL to dump exports from
+        /// Path to the DLL (or its .lib/.dll.a import library), .so or .dylib to dump exports from
         dll: PathBuf,
+        /// Print the exports as JSON instead of one per line
+        #[arg(long)]
+        json: bool,
     },
     /// Generate a new proxy DLL project for the given DLL file
     Generate {
-        /// Path to the DLL to proxy
-        dll: PathBuf,
+        /// Path to the DLL to proxy, or its .lib/.dll.a import library.
+        /// Pass both the x86 and x64 builds of the DLL to proxy both from one project.
+        /// With --workspace, pass every DLL the workspace should proxy
+        #[arg(value_name = "DLL", num_args = 1.., required = true)]
+        dlls: Vec<PathBuf>,
         /// Path to the DLL proxy project to create.
         project_dir: PathBuf,
+        /// Create a Cargo workspace with a proxy for each DLL, plus a shared `hooks` library crate for code they have in common
+        #[arg(long)]
+        workspace: bool,
+        /// Only proxy exports matching this glob (or regex, with a `re:` prefix). Can be repeated
+        #[arg(long, value_name = "PATTERN")]
+        include: Vec<ExportPattern>,
+        /// Don't proxy exports matching this glob (or regex, with a `re:` prefix). Can be repeated
+        #[arg(long, value_name = "PATTERN")]
+        exclude: Vec<ExportPattern>,
+        /// Leave out exports that the proxy can't provide, instead of failing
+        #[arg(long)]
+        allow_unprovided: bool,
+        /// Write into an existing folder, as long as none of the project's files already exist
+        #[arg(long, conflicts_with = "force")]
+        into_existing: bool,
+        /// Overwrite an existing proxygen project's generated files. Its hooks stay intercepted, and src/intercepted_exports.rs,
+        /// Cargo.toml, .gitignore and a hand-edited src/lib.rs are kept
+        #[arg(long)]
+        force: bool,
+        /// Version control to initialize the project with (git or none)
+        #[arg(long, default_value = "none")]
+        vcs: Vcs,
+        /// Where the proxy loads the original DLL from: sibling (`<name>_` next to the proxy, the default),
+        /// relative:NAME, system or env:VAR. Can be repeated, to try each in order until one loads
+        #[arg(long, value_name = "STRATEGY")]
+        original: Vec<LoadingStrategy>,
+        /// Print a unified diff of every file that would change, without writing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Template pack overriding some or all of the built in templates (see `proxygen templates export`)
+        #[arg(long, value_name = "DIR")]
+        templates: Option<PathBuf>,
     },
     /// Merges the given DLL's new exports into an existing DLL proxy project
     Merge {
-        /// Path to the DLL to proxy
-        dll: PathBuf,
+        /// Path to the DLL to proxy, or its .lib/.dll.a import library.
+        /// Pass both the x86 and x64 builds of the DLL to merge both, or a build for a new arch to add that arch
+        #[arg(value_name = "DLL", num_args = 1.., required = true)]
+        dlls: Vec<PathBuf>,
         /// Path to the proxy project into which new DLL exports should be merged.
+        /// For a workspace, each DLL is merged into the member proxying it
         project_dir: PathBuf,
+        /// Only proxy exports matching this glob (or regex, with a `re:` prefix). Can be repeated
+        #[arg(long, value_name = "PATTERN")]
+        include: Vec<ExportPattern>,
+        /// Don't proxy exports matching this glob (or regex, with a `re:` prefix). Can be repeated
+        #[arg(long, value_name = "PATTERN")]
+        exclude: Vec<ExportPattern>,
+        /// Leave out exports that the proxy can't provide, instead of failing
+        #[arg(long)]
+        allow_unprovided: bool,
+        /// Print a unified diff of every file that would change, without writing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Template pack overriding some or all of the built in templates (see `proxygen templates export`)
+        #[arg(long, value_name = "DIR")]
+        templates: Option<PathBuf>,
+        /// Exit with an error if the project is out of date, without writing anything
+        #[arg(long, conflicts_with = "dry_run")]
+        check: bool,
+        /// Remove the exports the DLL no longer has, instead of keeping them
+        #[arg(long)]
+        prune: bool,
     },
-    /// Updates an exisitng DLL proxy project's exports based on the intercepted exports
+    /// Audits a DLL for traits that need special care when loading it from a proxy
+    Audit {
+        /// Path to the original DLL to audit
+        dll: PathBuf,
+    },
+    /// Summarizes every DLL in a directory (recursively), to help pick a DLL to proxy
+    Scan {
+        /// Directory to scan
+        dir: PathBuf,
+        /// Print the summary as JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Updates an existing DLL proxy project's exports based on the intercepted exports
     Update {
-        /// Path to the proxy project to update. The original DLL is expected to have an underscore suffix
+        /// Path to the proxy project to update, or a workspace to update every member of.
+        /// The original DLL is loaded using the strategy in the project's proxygen.toml (see `generate --original`)
+        project_dir: PathBuf,
+        /// Print a unified diff of every file that would change, without writing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Template pack overriding some or all of the built in templates (see `proxygen templates export`)
+        #[arg(long, value_name = "DIR")]
+        templates: Option<PathBuf>,
+        /// Exit with an error if the project is out of date, without writing anything
+        #[arg(long, conflicts_with = "dry_run")]
+        check: bool,
+    },
+    /// Cross-checks a DLL proxy project's generated files, manifest and hooks, and reports every inconsistency with its file
+    /// and line
+    Check {
+        /// Path to the proxy project to check, or a workspace to check every member of
+        project_dir: PathBuf,
+        /// The original DLL to check the project's exports against. Can be repeated, eg. for the x86 and x64 builds.
+        /// For a workspace, each DLL is checked against the member proxying it
+        #[arg(long = "dll", value_name = "DLL")]
+        dlls: Vec<PathBuf>,
+        /// Template pack overriding some or all of the built in templates (see `proxygen templates export`)
+        #[arg(long, value_name = "DIR")]
+        templates: Option<PathBuf>,
+    },
+    /// Upgrades a DLL proxy project generated by an older version of proxygen to this version's generated files and
+    /// dependencies, keeping its hooks
+    Upgrade {
+        /// Path to the proxy project to upgrade, or a workspace to upgrade every member of
         project_dir: PathBuf,
+        /// Print a unified diff of every file that would change, without writing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Template pack overriding some or all of the built in templates (see `proxygen templates export`)
+        #[arg(long, value_name = "DIR")]
+        templates: Option<PathBuf>,
+    },
+    /// Works with template packs, which customize the generated project's files
+    Templates {
+        #[command(subcommand)]
+        command: TemplatesCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum TemplatesCommands {
+    /// Writes the built in templates to a folder, as a starting point for a template pack
+    Export {
+        /// Folder to write the template pack to
+        dir: PathBuf,
     },
 }
 
+/// Gets a file's exports, logging what kind of file it is along the way
+fn get_exports(dll_file: &DLLFile) -> Result<BTreeSet<ExportName>> {
+    println!(
+        "Getting exports for {}",
+        dll_file.get_path().to_string_lossy()
+    );
+    println!("Detected arch: {}", dll_file.get_arch_name()?);
+    if dll_file.is_import_library() {
+        println!("Detected import library for {}", dll_file.get_dll_name()?);
+    }
+    for (architecture, dylib) in dll_file.get_reexported_dylibs() {
+        println!("{:?} re-exports everything from {}", architecture, dylib);
+    }
+    Ok(dll_file.parse_exports()?)
+}
+
+/// Describes an export on one line, eg. `memcpy@@GLIBC_2.14 [weak] [data] -> NTDLL.RtlCopyMemory`
+fn describe_export(export: &ExportName) -> String {
+    let mut description = export.original.clone();
+    // Default versions aren't part of the name, since they're what the linker picks without one
+    if let Some(version) = export
+        .version
+        .as_ref()
+        .filter(|_| !export.original.contains('@'))
+    {
+        description.push_str(&format!("@@{}", version));
+    }
+    let flags = [
+        (export.noname, "noname"),
+        (export.weak, "weak"),
+        (export.unique, "unique"),
+        (export.protected, "protected"),
+        (export.kind == ExportKind::Data, "data"),
+    ];
+    for (_, flag) in flags.iter().filter(|(set, _)| *set) {
+        description.push_str(&format!(" [{}]", flag));
+    }
+    if let Some(forwarder) = &export.forwarder {
+        description.push_str(&format!(" -> {}", forwarder));
+    }
+    description
+}
+
+/// A DLL's name, and the exports of each of its builds
+type DllBuilds = (String, Vec<ArchExports>);
+
+/// Reads the exports of each DLL, grouping the builds of the same DLL (by name) together
+fn read_dlls(dlls: &[PathBuf]) -> Result<Vec<DllBuilds>> {
+    let mut groups: Vec<DllBuilds> = Vec::new();
+    for dll in dlls {
+        let dll_file = DLLFile::new(dll)?;
+        let name = dll_file.get_dll_name()?;
+        // Only DLLs (and their import libraries) can be proxied
+        let arch = dll_file.get_arch()?;
+        let original = ArchExports::new(arch, get_exports(&dll_file)?, dll_file.get_sha256());
+        match groups
+            .iter_mut()
+            .find(|(other, _)| other.eq_ignore_ascii_case(&name))
+        {
+            Some((_, originals)) => originals.push(original),
+            None => groups.push((name, vec![original])),
+        }
+    }
+    Ok(groups)
+}
+
+/// Reads the exports of each build of the original DLL, which all have to share a name
+fn read_originals(dlls: &[PathBuf]) -> Result<DllBuilds> {
+    let mut groups = read_dlls(dlls)?.into_iter();
+    let (dll_name, originals) = groups.next().unwrap_or_default();
+    if let Some((other, _)) = groups.next() {
+        return Err(proxygen::Error::MismatchedDllNames(dll_name, other).into());
+    }
+    Ok((dll_name, originals))
+}
+
+/// The member proxies of the workspace at `dir`, or `None` if `dir` is a proxy project rather than a workspace root
+fn workspace_root_proxies(dir: &Path) -> Result<Option<Vec<PathBuf>>> {
+    if dir.join(MANIFEST_FILE).is_file() {
+        return Ok(None);
+    }
+    Ok(proxygen::proxy::workspace_proxies(dir)?)
+}
+
+/// Loads the template pack at `dir` if given, or the built in templates
+fn load_templates(dir: &Option<PathBuf>) -> Result<ProxyTemplates> {
+    Ok(match dir {
+        Some(dir) => ProxyTemplates::from_dir(dir)?,
+        None => ProxyTemplates::new()?,
+    })
+}
+
+/// Reports what generating a project left out, or lists the unprovidable exports before failing
+fn report_generation(result: proxygen::Result<GenerationReport>) -> Result<GenerationReport> {
+    let report = result.map_err(generation_error)?;
+    print_notes(&report);
+    Ok(report)
+}
+
+/// Prints what generating a project left out, forwarded, or only proxies on one arch, and warns if the project's macros
+/// are from a different proxygen version
+fn print_notes(report: &GenerationReport) {
+    if let Some(macros_version) = report.project.macros_mismatch() {
+        eprintln!(
+            "Warning: DLL proxy project '{}' depends on proxygen-macros {}, but this is proxygen {}. The generated files may not match its macros, so run `proxygen upgrade` on it",
+            report.project.package_name,
+            macros_version,
+            env!("CARGO_PKG_VERSION")
+        );
+    }
+    for unprovidable in report.left_out.iter() {
+        eprintln!(
+            "The proxy can't provide export '{}', because {}",
+            unprovidable.export.original, unprovidable.reason
+        );
+    }
+    if !report.left_out.is_empty() {
+        eprintln!(
+            "Leaving out {} export(s) that can't be provided by the proxy",
+            report.left_out.len()
+        );
+    }
+    let forwarded: BTreeSet<String> = report
+        .project
+        .arches()
+        .into_iter()
+        .flat_map(|arch| arch.forwarded_exports.into_iter())
+        .map(|export| export.original)
+        .collect();
+    if !forwarded.is_empty() {
+        println!(
+            "Forwarding {} excluded export(s) straight to the original DLL",
+            forwarded.len()
+        );
+    }
+    let arch_only = report.project.arch_only_exports();
+    for (export, arch) in arch_only.iter() {
+        eprintln!(
+            "Export '{}' only exists in the {:?} DLL",
+            export.original, arch
+        );
+    }
+    if !arch_only.is_empty() {
+        eprintln!(
+            "{} export(s) only exist in one arch's DLL. They're only proxied on that arch, so hooks for them need a matching #[cfg(target_arch = \"...\")]",
+            arch_only.len()
+        );
+    }
+}
+
+/// Turns a generation error into the message to abort with, listing the unprovidable exports first
+fn generation_error(err: proxygen::Error) -> anyhow::Error {
+    match err {
+        proxygen::Error::UnprovidableExports(unprovidable) => {
+            for unprovidable in unprovidable.iter() {
+                eprintln!(
+                    "The proxy can't provide export '{}', because {}",
+                    unprovidable.export.original, unprovidable.reason
+                );
+            }
+            anyhow::anyhow!(
+                "{} export(s) can't be provided by the proxy. Pass --allow-unprovided to leave them out. Aborting",
+                unprovidable.len()
+            )
+        }
+        err @ proxygen::Error::ProjectExists(_) => anyhow::anyhow!(
+            "{}. Pass --into-existing or --force to write into it. Aborting",
+            err
+        ),
+        err => anyhow::anyhow!("{}. Aborting", err),
+    }
+}
+
+/// Reports the exports a merge found missing from the DLL, and the hooks that intercept them
+fn print_missing(report: &GenerationReport, prune: bool) {
+    for export_name in report.missing.iter() {
+        eprintln!("Export '{}' is no longer in the DLL", export_name);
+    }
+    if !report.missing.is_empty() && !prune {
+        eprintln!(
+            "Keeping {} export(s) that are no longer in the DLL, which won't resolve to anything. Pass --prune to remove them",
+            report.missing.len()
+        );
+    }
+    for export_name in report.orphaned_hooks.iter() {
+        eprintln!(
+            "Export '{}' is hooked, but is no longer in the DLL. Remove or rework its hook",
+            export_name
+        );
+    }
+}
+
+/// Prints how many files and exports changed, listing the changed exports if `list_exports` is set
+fn print_summary(report: &GenerationReport, list_exports: bool) {
+    if list_exports {
+        for export_name in report.added.iter() {
+            println!("Added export: {}", export_name);
+        }
+        for export_name in report.removed.iter() {
+            println!("Removed export: {}", export_name);
+        }
+        for export_name in report.newly_intercepted.iter() {
+            println!("Newly intercepted export: {}", export_name);
+        }
+    }
+    println!(
+        "{} file(s) changed, {} export(s) added, {} removed, {} newly intercepted",
+        report.changes.len(),
+        report.added.len(),
+        report.removed.len(),
+        report.newly_intercepted.len()
+    );
+}
+
+/// Prints the diff of every file a dry run would have changed, followed by the summary
+fn print_dry_run(report: &GenerationReport, list_exports: bool) {
+    for change in report.changes.iter() {
+        print!("{}", change.unified_diff());
+    }
+    print_summary(report, list_exports);
+    println!("Dry run, nothing was written");
+}
+
+/// Prints what an update found and did, or fails if `--check` found the project out of date
+fn report_update(
+    report: &GenerationReport,
+    project_dir: &Path,
+    dry_run: bool,
+    check: bool,
+) -> Result<()> {
+    if check {
+        if report.is_up_to_date() {
+            println!(
+                "DLL proxy project '{}' is up to date",
+                report.project.package_name
+            );
+            return Ok(());
+        }
+        for change in report.changes.iter() {
+            println!("Out of date: {}", change.path.to_string_lossy());
+        }
+        print_summary(report, true);
+        return Err(anyhow::anyhow!(
+            "DLL proxy project '{}' at {} is out of date",
+            report.project.package_name,
+            project_dir.to_string_lossy()
+        ));
+    }
+    let mut intercepted: Vec<&String> = report.project.intercepted_exports.iter().collect();
+    intercepted.sort();
+    for export_name in intercepted {
+        println!("Detected intercepted export: {}", export_name);
+    }
+    if dry_run {
+        print_dry_run(report, true);
+        return Ok(());
+    }
+    print_summary(report, true);
+    println!(
+        "Successfully updated DLL proxy project '{}' at {}",
+        report.project.package_name,
+        project_dir.to_string_lossy()
+    );
+    Ok(())
+}
+
+/// Prints what an upgrade changed, and the dependencies it removed that hooks might still use
+fn report_upgrade(report: &UpgradeReport, project_dir: &Path, dry_run: bool) {
+    for dependency in report.removed_dependencies.iter() {
+        eprintln!(
+            "Removed the '{}' dependency, which the generated files no longer use. If your hooks use it, add it back to Cargo.toml",
+            dependency
+        );
+    }
+    for module in report.kept_modules.iter() {
+        println!("Kept your 'mod {};' in src/lib.rs", module);
+    }
+    for path in report.not_upgraded.iter() {
+        eprintln!(
+            "{} was changed by hand, so it wasn't upgraded. Compare it with the `lib.rs` from `proxygen templates export` and port it over yourself",
+            path.to_string_lossy()
+        );
+    }
+    if dry_run {
+        for change in report.changes.iter() {
+            print!("{}", change.unified_diff());
+        }
+        println!("{} file(s) changed", report.changes.len());
+        println!("Dry run, nothing was written");
+        return;
+    }
+    for change in report.changes.iter() {
+        println!("Upgraded {}", change.path.to_string_lossy());
+    }
+    println!(
+        "Successfully upgraded DLL proxy project '{}' at {} to proxygen {}",
+        report.project.package_name,
+        project_dir.to_string_lossy(),
+        env!("CARGO_PKG_VERSION")
+    );
+}
+
+/// Prints the issues `check` found, with their paths relative to `root`, failing if there were any
+fn report_check(issues: &[Issue], project_dir: &Path, root: &Path) -> Result<()> {
+    let prefix = project_dir.strip_prefix(root).unwrap_or(project_dir);
+    for issue in issues.iter() {
+        let issue = Issue {
+            path: prefix.join(&issue.path),
+            ..issue.clone()
+        };
+        println!("{}", issue);
+    }
+    if issues.is_empty() {
+        println!(
+            "DLL proxy project at {} is consistent",
+            project_dir.to_string_lossy()
+        );
+        return Ok(());
+    }
+    Err(anyhow::anyhow!(
+        "Found {} issue(s) in DLL proxy project at {}",
+        issues.len(),
+        project_dir.to_string_lossy()
+    ))
+}
+
+/// Runs the update of each project, failing with the only error if there was one, or how many failed if there were more.
+/// Every project is updated (or checked) even if an earlier one fails
+fn report_updates(updates: impl Iterator<Item = Result<()>>) -> Result<()> {
+    let mut errors = Vec::new();
+    let mut total = 0;
+    for update in updates {
+        total += 1;
+        if let Err(err) = update {
+            errors.push(err);
+        }
+    }
+    if errors.len() <= 1 {
+        return errors.pop().map_or(Ok(()), Err);
+    }
+    for err in errors.iter() {
+        eprintln!("{}", err);
+    }
+    Err(anyhow::anyhow!(
+        "{} of {} project(s) failed",
+        errors.len(),
+        total
+    ))
+}
+
 fn main() -> Result<()> {
     let cli = Cli::parse();
     match &cli.command {
-        Commands::DumpExports { dll } => {
+        Commands::DumpExports { dll, json } => {
             let dll_file = DLLFile::new(dll)?;
-            for export in dll_file.get_exports()? {
-                println!("{}", export.original);
+            if *json {
+                let exports: Vec<ExportName> = dll_file.parse_exports()?.into_iter().collect();
+                println!("{}", serde_json::to_string_pretty(&exports)?);
+            } else {
+                for export in get_exports(&dll_file)? {
+                    println!("{}", describe_export(&export));
+                }
             }
         }
-        Commands::Generate { dll, project_dir } => {
+        Commands::Generate {
+            dlls,
+            project_dir,
+            workspace,
+            include,
+            exclude,
+            allow_unprovided,
+            into_existing,
+            force,
+            vcs,
+            original,
+            dry_run,
+            templates,
+        } => {
+            let options = GenerationOptions {
+                filter: ExportFilter::new(include.clone(), exclude.clone()),
+                allow_unprovided: *allow_unprovided,
+                templates: load_templates(templates)?,
+                dry_run: *dry_run,
+                prune: false,
+            };
+            let scaffold = ScaffoldOptions {
+                mode: match (*into_existing, *force) {
+                    (_, true) => ScaffoldMode::Force,
+                    (true, false) => ScaffoldMode::IntoExisting,
+                    (false, false) => ScaffoldMode::New,
+                },
+                vcs: *vcs,
+                loading: LoadingStrategy::from_fallbacks(original.clone()),
+            };
+            if *workspace {
+                let originals = read_dlls(dlls)?;
+                let report =
+                    proxygen::create_proxy_workspace(&originals, project_dir, &options, scaffold)
+                        .map_err(generation_error)?;
+                for member in report.members.iter() {
+                    println!(
+                        "Proxying {} in workspace member '{}'",
+                        member.project.dll_name, member.project.package_name
+                    );
+                    print_notes(member);
+                }
+                if *dry_run {
+                    for change in report.changes.iter() {
+                        print!("{}", change.unified_diff());
+                    }
+                    for member in report.members.iter() {
+                        for change in member.changes.iter() {
+                            print!("{}", change.unified_diff());
+                        }
+                    }
+                    println!("{} workspace file(s) changed", report.changes.len());
+                    for member in report.members.iter() {
+                        print!("{}: ", member.project.package_name);
+                        print_summary(member, false);
+                    }
+                    println!("Dry run, nothing was written");
+                    return Ok(());
+                }
+                println!(
+                    "Successfully created new DLL proxy workspace with {} member(s) at {}",
+                    report.members.len(),
+                    project_dir.to_string_lossy()
+                );
+                return Ok(());
+            }
+            if dlls.len() > 2 {
+                return Err(anyhow::anyhow!(
+                    "A project proxies the x86 and x64 builds of one DLL. Pass --workspace to proxy several DLLs. Aborting"
+                ));
+            }
+            let (dll_name, originals) = read_originals(dlls)?;
+            if dll_name.contains('-') {
+                eprintln!(
+                    "Detected hyphens in DLL name - the generated project will use underscores instead."
+                );
+            }
+            let report = report_generation(proxygen::create_proxy_project(
+                &originals,
+                dll_name,
+                project_dir,
+                &options,
+                scaffold,
+            ))?;
+            if *dry_run {
+                print_dry_run(&report, false);
+                return Ok(());
+            }
+            println!(
+                "The generated project will use the {} target (can be changed in .cargo/config)",
+                report.project.target()
+            );
+            if let Some(other_arch) = report.project.other_arch.as_ref() {
+                println!(
+                    "Build the {:?} proxy with `cargo build --target {}`",
+                    other_arch.arch,
+                    other_arch.target()
+                );
+            }
+            println!(
+                "Successfully created new DLL proxy project '{}' at {}",
+                report.project.package_name,
+                project_dir.to_string_lossy()
+            );
+        }
+        Commands::Merge {
+            dlls,
+            project_dir,
+            include,
+            exclude,
+            allow_unprovided,
+            dry_run,
+            templates,
+            check,
+            prune,
+        } => {
+            let project_dir = project_dir.canonicalize()?;
+            let targets = match workspace_root_proxies(&project_dir)? {
+                Some(_) => read_dlls(dlls)?
+                    .into_iter()
+                    .map(|(dll_name, originals)| {
+                        Ok((
+                            proxygen::proxy::find_workspace_proxy(&project_dir, &dll_name)?,
+                            originals,
+                        ))
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+                None => vec![(project_dir, read_originals(dlls)?.1)],
+            };
+            let options = GenerationOptions {
+                filter: ExportFilter::new(include.clone(), exclude.clone()),
+                allow_unprovided: *allow_unprovided,
+                templates: load_templates(templates)?,
+                dry_run: *dry_run || *check,
+                prune: *prune,
+            };
+            report_updates(targets.iter().map(|(project_dir, originals)| {
+                let report = report_generation(proxygen::update_proxy_project(
+                    originals,
+                    project_dir,
+                    &options,
+                ))?;
+                print_missing(&report, *prune);
+                report_update(&report, project_dir, *dry_run, *check)
+            }))?;
+        }
+        Commands::Audit { dll } => {
             let dll_file = DLLFile::new(dll)?;
-            if let Some(dll_name) = dll
-                .file_name()
-                .expect("Expected path to end with a file name")
-                .to_str()
-            {
-                proxy::create_proxy_project(
-                    &dll_file.get_exports()?,
-                    dll_name,
-                    &project_dir,
-                    dll_file.get_arch()?,
-                )?;
+            let dll_name = dll_file.get_dll_name()?;
+            println!("Auditing {} ({:?})", dll_name, dll_file.get_arch()?);
+            let findings = audit::audit_dll(dll_file.get_pe()?, &dll_name)?;
+            for finding in findings.iter() {
+                let severity = match finding.severity {
+                    audit::Severity::Warning => "warning",
+                    audit::Severity::Note => "note",
+                };
+                println!("{}: {}: {}", severity, finding.title, finding.detail);
+            }
+            let warnings = findings
+                .iter()
+                .filter(|finding| finding.severity == audit::Severity::Warning)
+                .count();
+            if warnings == 0 {
+                println!("Nothing that should get in the way of loading {}_ from the proxy", dll_name);
             } else {
-                return Err(anyhow::anyhow!("Failed to get dll name from path"));
+                println!("{} warning(s) for loading {}_ from the proxy", warnings, dll_name);
             }
         }
-        Commands::Merge { dll, project_dir } => {
-            let dll_file = DLLFile::new(dll)?;
-            proxy::update_proxy_project(&dll_file.get_exports()?, &project_dir.canonicalize()?)?;
+        Commands::Scan { dir, json } => {
+            let summaries = scan::scan_directory(dir)?;
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&summaries)?);
+            } else {
+                scan::print_table(&summaries);
+            }
+        }
+        Commands::Update {
+            project_dir,
+            dry_run,
+            templates,
+            check,
+        } => {
+            let project_dir = project_dir.canonicalize()?;
+            let project_dirs =
+                workspace_root_proxies(&project_dir)?.unwrap_or_else(|| vec![project_dir]);
+            let options = GenerationOptions {
+                filter: ExportFilter::default(),
+                allow_unprovided: false,
+                templates: load_templates(templates)?,
+                dry_run: *dry_run || *check,
+                prune: false,
+            };
+            report_updates(project_dirs.iter().map(|project_dir| {
+                let report =
+                    report_generation(proxygen::update_proxy_project(&[], project_dir, &options))?;
+                report_update(&report, project_dir, *dry_run, *check)
+            }))?;
+        }
+        Commands::Check {
+            project_dir,
+            dlls,
+            templates,
+        } => {
+            let project_dir = project_dir.canonicalize()?;
+            // Each project to check, and the original DLL to check it against
+            let mut targets: Vec<(PathBuf, Option<DllBuilds>)> =
+                workspace_root_proxies(&project_dir)?
+                    .unwrap_or_else(|| vec![project_dir.clone()])
+                    .into_iter()
+                    .map(|member| (member, None))
+                    .collect();
+            for (dll_name, originals) in read_dlls(dlls)? {
+                let member = match targets.as_slice() {
+                    [(member, _)] if *member == project_dir => member.clone(),
+                    _ => proxygen::proxy::find_workspace_proxy(&project_dir, &dll_name)?,
+                };
+                if let Some((_, original)) = targets.iter_mut().find(|(other, _)| *other == member)
+                {
+                    if let Some((other, _)) = original {
+                        return Err(
+                            proxygen::Error::MismatchedDllNames(other.clone(), dll_name).into()
+                        );
+                    }
+                    *original = Some((dll_name, originals));
+                }
+            }
+            let templates = load_templates(templates)?;
+            report_updates(targets.iter().map(|(member, original)| {
+                let issues = check::check_project(member, original.as_ref(), &templates)
+                    .map_err(generation_error)?;
+                report_check(&issues, member, &project_dir)
+            }))?;
+        }
+        Commands::Upgrade {
+            project_dir,
+            dry_run,
+            templates,
+        } => {
+            let project_dir = project_dir.canonicalize()?;
+            let project_dirs =
+                workspace_root_proxies(&project_dir)?.unwrap_or_else(|| vec![project_dir]);
+            let options = GenerationOptions {
+                filter: ExportFilter::default(),
+                allow_unprovided: false,
+                templates: load_templates(templates)?,
+                dry_run: *dry_run,
+                prune: false,
+            };
+            report_updates(project_dirs.iter().map(|project_dir| {
+                let report = proxygen::upgrade_proxy_project(project_dir, &options)
+                    .map_err(generation_error)?;
+                report_upgrade(&report, project_dir, *dry_run);
+                Ok(())
+            }))?;
         }
-        Commands::Update { project_dir } => {
-            proxy::update_proxy_project(&BTreeSet::new(), &project_dir.canonicalize()?)?;
+        Commands::Templates {
+            command: TemplatesCommands::Export { dir },
+        } => {
+            let files = ProxyTemplates::export_builtin()?;
+            let existing: Vec<String> = files
+                .iter()
+                .filter(|(path, _)| dir.join(path).exists())
+                .map(|(path, _)| path.to_string_lossy().to_string())
+                .collect();
+            if !existing.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "Refusing to overwrite existing file(s) in {}: {}. Aborting",
+                    dir.to_string_lossy(),
+                    existing.join(", ")
+                ));
+            }
+            files.write_to(dir)?;
+            println!(
+                "Exported the built in templates (template pack version {}) to {}",
+                proxygen::proxy::TEMPLATE_PACK_VERSION,
+                dir.to_string_lossy()
+            );
+            println!("Delete any templates you don't want to override, then pass --templates to generate, merge or update");
         }
     }
 
@@ -0,0 +1,348 @@
+use exe::Arch;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+use crate::{
+    exports::{ExportKind, ExportName},
+    project::{ArchExports, LoadingStrategy, ProxyProject},
+    Error, Result,
+};
+
+/// The project manifest's file name, relative to the project root
+pub const MANIFEST_FILE: &str = "proxygen.toml";
+
+/// `proxygen.toml`, the source of truth for every generated file in a project.
+///
+/// Intercepted exports aren't recorded, since they come from `src/intercepted_exports.rs` itself.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Manifest {
+    /// The proxygen version that last wrote the manifest
+    proxygen: String,
+    /// The shared hooks crate of the workspace the project is a member of
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hooks_crate: Option<String>,
+    /// The `proxygen-macros` version the project's `Cargo.toml` depends on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    macros: Option<String>,
+    original: ManifestOriginal,
+    #[serde(default)]
+    exports: Vec<ManifestExport>,
+    /// The original DLL's build for the other arch, in projects that proxy both
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    other_arch: Option<ManifestArch>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ManifestArch {
+    /// `x86` or `x64`
+    arch: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+    #[serde(default)]
+    exports: Vec<ManifestExport>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ManifestOriginal {
+    /// The original DLL's file name, eg. `version.dll`
+    name: String,
+    /// Hex encoded SHA-256 of the original DLL, unless the project was generated from an import library
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+    /// `x86` or `x64`
+    arch: String,
+    #[serde(default)]
+    loading: LoadingStrategy,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ManifestExport {
+    name: String,
+    /// The Rust identifier the export's thunk and index are generated with
+    identifier: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ordinal: Option<u16>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    noname: bool,
+    #[serde(default)]
+    kind: ManifestExportKind,
+    /// The original DLL's own forwarder target for this export, eg. `NTDLL.RtlAllocateHeap`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    forwarder: Option<String>,
+    /// Whether the export was filtered out, and is forwarded to the original DLL instead of being proxied
+    #[serde(default, skip_serializing_if = "is_false")]
+    forward_to_original: bool,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ManifestExportKind {
+    #[default]
+    Code,
+    Data,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+impl ManifestExport {
+    fn new(export: &ExportName, forward_to_original: bool) -> Self {
+        Self {
+            name: export.original.clone(),
+            identifier: export.cleaned.clone(),
+            ordinal: export.ordinal,
+            noname: export.noname,
+            kind: match export.kind {
+                ExportKind::Code => ManifestExportKind::Code,
+                ExportKind::Data => ManifestExportKind::Data,
+            },
+            forwarder: export.forwarder.clone(),
+            forward_to_original,
+        }
+    }
+
+    fn to_export(&self) -> ExportName {
+        let mut export = ExportName::new(&self.name);
+        export.cleaned = self.identifier.clone();
+        export.ordinal = self.ordinal;
+        export.noname = self.noname;
+        export.kind = match self.kind {
+            ManifestExportKind::Code => ExportKind::Code,
+            ManifestExportKind::Data => ExportKind::Data,
+        };
+        export.forwarder = self.forwarder.clone();
+        export
+    }
+}
+
+fn arch_name(arch: Arch) -> String {
+    match arch {
+        Arch::X86 => "x86",
+        Arch::X64 => "x64",
+    }
+    .into()
+}
+
+fn parse_arch(arch: &str) -> Result<Arch> {
+    match arch {
+        "x86" => Ok(Arch::X86),
+        "x64" => Ok(Arch::X64),
+        arch => Err(Error::InvalidManifest(format!("unknown arch '{}'", arch))),
+    }
+}
+
+fn manifest_exports(
+    exports: &BTreeSet<ExportName>,
+    forwarded_exports: &BTreeSet<ExportName>,
+) -> Vec<ManifestExport> {
+    let mut manifest_exports: Vec<ManifestExport> = exports
+        .iter()
+        .map(|export| ManifestExport::new(export, false))
+        .chain(
+            forwarded_exports
+                .iter()
+                .map(|export| ManifestExport::new(export, true)),
+        )
+        .collect();
+    manifest_exports.sort_by(|a, b| a.name.cmp(&b.name));
+    manifest_exports
+}
+
+/// The proxied exports, and the exports forwarded to the original DLL
+fn split_exports(exports: &[ManifestExport]) -> (BTreeSet<ExportName>, BTreeSet<ExportName>) {
+    let mut proxied_exports = BTreeSet::new();
+    let mut forwarded_exports = BTreeSet::new();
+    for export in exports.iter() {
+        if export.forward_to_original {
+            forwarded_exports.insert(export.to_export());
+        } else {
+            proxied_exports.insert(export.to_export());
+        }
+    }
+    (proxied_exports, forwarded_exports)
+}
+
+impl Manifest {
+    /// Records the project, as written by this version of proxygen
+    pub(crate) fn new(project: &ProxyProject) -> Self {
+        Self {
+            proxygen: env!("CARGO_PKG_VERSION").into(),
+            hooks_crate: project.hooks_crate.clone(),
+            macros: project.macros_version.clone(),
+            original: ManifestOriginal {
+                name: project.dll_name.clone(),
+                sha256: project.original_sha256.clone(),
+                arch: arch_name(project.arch),
+                loading: project.loading.clone(),
+            },
+            exports: manifest_exports(&project.exports, &project.forwarded_exports),
+            other_arch: project.other_arch.as_ref().map(|other_arch| ManifestArch {
+                arch: arch_name(other_arch.arch),
+                sha256: other_arch.original_sha256.clone(),
+                exports: manifest_exports(&other_arch.exports, &other_arch.forwarded_exports),
+            }),
+        }
+    }
+
+    pub(crate) fn parse(manifest: &str) -> Result<Self> {
+        toml::from_str(manifest).map_err(|err| Error::InvalidManifest(err.to_string()))
+    }
+
+    pub(crate) fn render(&self) -> Result<String> {
+        let manifest =
+            toml::to_string(self).map_err(|err| Error::InvalidManifest(err.to_string()))?;
+        Ok(format!(
+            "# This file is generated by proxygen. It's the source of truth for the project's generated files,\n\
+             # which `proxygen update` regenerates from it\n{}",
+            manifest
+        ))
+    }
+
+    /// The proxygen version that last wrote the manifest
+    pub(crate) fn generator_version(&self) -> &str {
+        &self.proxygen
+    }
+
+    pub(crate) fn dll_name(&self) -> &str {
+        &self.original.name
+    }
+
+    pub(crate) fn sha256(&self) -> Option<&str> {
+        self.original.sha256.as_deref()
+    }
+
+    pub(crate) fn loading(&self) -> &LoadingStrategy {
+        &self.original.loading
+    }
+
+    pub(crate) fn macros_version(&self) -> Option<&str> {
+        self.macros.as_deref()
+    }
+
+    pub(crate) fn hooks_crate(&self) -> Option<&str> {
+        self.hooks_crate.as_deref()
+    }
+
+    pub(crate) fn arch(&self) -> Result<Arch> {
+        parse_arch(&self.original.arch)
+    }
+
+    /// The proxied exports, and the exports forwarded to the original DLL
+    pub(crate) fn exports(&self) -> (BTreeSet<ExportName>, BTreeSet<ExportName>) {
+        split_exports(&self.exports)
+    }
+
+    /// The original DLL's build for the other arch, if the project proxies both
+    pub(crate) fn other_arch(&self) -> Result<Option<ArchExports>> {
+        let Some(other_arch) = self.other_arch.as_ref() else {
+            return Ok(None);
+        };
+        let arch = parse_arch(&other_arch.arch)?;
+        if arch == self.arch()? {
+            return Err(Error::InvalidManifest(format!(
+                "both arches are {}",
+                other_arch.arch
+            )));
+        }
+        let (exports, forwarded_exports) = split_exports(&other_arch.exports);
+        Ok(Some(ArchExports {
+            arch,
+            exports,
+            forwarded_exports,
+            original_sha256: other_arch.sha256.clone(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every field of an export, which its equality doesn't cover
+    fn details(exports: &BTreeSet<ExportName>) -> Vec<String> {
+        exports
+            .iter()
+            .map(|export| format!("{:?}", export))
+            .collect()
+    }
+
+    #[test]
+    fn round_trips_projects() {
+        let exports = BTreeSet::from([
+            ExportName {
+                ordinal: Some(1),
+                ..ExportName::new("?foo@@YAXXZ")
+            },
+            ExportName {
+                kind: ExportKind::Data,
+                ..ExportName::new("Counter")
+            },
+            ExportName {
+                forwarder: Some("NTDLL.RtlAllocateHeap".into()),
+                ..ExportName::new("HeapAlloc")
+            },
+        ]);
+        let mut project = ProxyProject::new("proxy", "foo.dll", Arch::X64, exports);
+        project.forwarded_exports.insert(ExportName {
+            ordinal: Some(4),
+            noname: true,
+            ..ExportName::new("Ordinal4")
+        });
+        project.original_sha256 = Some("abcd".into());
+        project.loading = "relative:originals/foo.dll".parse().unwrap();
+        project.hooks_crate = Some("hooks".into());
+        let mut other_arch =
+            ArchExports::new(Arch::X86, BTreeSet::from([ExportName::new("_Bar@8")]), None);
+        other_arch.forwarded_exports.insert(ExportName::new("Baz"));
+        project.other_arch = Some(other_arch);
+
+        let manifest = Manifest::parse(&Manifest::new(&project).render().unwrap()).unwrap();
+        assert_eq!(manifest.generator_version(), env!("CARGO_PKG_VERSION"));
+        assert_eq!(manifest.dll_name(), "foo.dll");
+        assert_eq!(manifest.sha256(), Some("abcd"));
+        assert_eq!(manifest.loading(), &project.loading);
+        assert_eq!(manifest.macros_version(), Some(env!("CARGO_PKG_VERSION")));
+        assert_eq!(manifest.hooks_crate(), Some("hooks"));
+        assert_eq!(manifest.arch().unwrap(), Arch::X64);
+        let (exports, forwarded_exports) = manifest.exports();
+        assert_eq!(details(&exports), details(&project.exports));
+        assert_eq!(
+            details(&forwarded_exports),
+            details(&project.forwarded_exports)
+        );
+        let other_arch = manifest.other_arch().unwrap().unwrap();
+        let expected = project.other_arch.as_ref().unwrap();
+        assert_eq!(other_arch.arch, Arch::X86);
+        assert_eq!(other_arch.original_sha256, None);
+        assert_eq!(details(&other_arch.exports), details(&expected.exports));
+        assert_eq!(
+            details(&other_arch.forwarded_exports),
+            details(&expected.forwarded_exports)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_arches() {
+        let original = "[original]\nname = \"foo.dll\"\narch = \"x64\"\n";
+        let manifest = Manifest::parse(&format!(
+            "proxygen = \"0.6.0\"\n[other_arch]\narch = \"x64\"\n{}",
+            original
+        ))
+        .unwrap();
+        assert!(matches!(
+            manifest.other_arch(),
+            Err(Error::InvalidManifest(_))
+        ));
+        let manifest = Manifest::parse(&format!(
+            "proxygen = \"0.6.0\"\n{}",
+            original.replace("x64", "arm64")
+        ))
+        .unwrap();
+        assert!(matches!(manifest.arch(), Err(Error::InvalidManifest(_))));
+        assert!(matches!(
+            Manifest::parse("proxygen = \"0.6.0\"\n"),
+            Err(Error::InvalidManifest(_))
+        ));
+    }
+}
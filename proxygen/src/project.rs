@@ -0,0 +1,1139 @@
+use exe::Arch;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    exports::{
+        find_unprovidable_exports, ExportKind, ExportName, UnprovidableExport, UnprovidableReason,
+        RESERVED_SYMBOL_PREFIX,
+    },
+    filter::ExportFilter,
+    hooks::find_intercepted_exports,
+    manifest::{Manifest, MANIFEST_FILE},
+    proxy::ProxyTemplates,
+    Error, Result,
+};
+
+/// A set of generated files, keyed by their path relative to the project root
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProjectFiles {
+    files: BTreeMap<PathBuf, String>,
+}
+
+impl ProjectFiles {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file, replacing any existing file at the same path
+    pub fn insert(&mut self, path: impl Into<PathBuf>, contents: impl Into<String>) {
+        self.files.insert(path.into(), contents.into());
+    }
+
+    /// Removes the file at the given relative path, returning its contents
+    pub fn remove(&mut self, path: impl AsRef<Path>) -> Option<String> {
+        self.files.remove(path.as_ref())
+    }
+
+    /// Gets the contents of the file at the given relative path
+    pub fn get(&self, path: impl AsRef<Path>) -> Option<&str> {
+        self.files.get(path.as_ref()).map(String::as_str)
+    }
+
+    /// Iterates over the files in path order
+    pub fn iter(&self) -> impl Iterator<Item = (&Path, &str)> {
+        self.files
+            .iter()
+            .map(|(path, contents)| (path.as_path(), contents.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Compares the files against what's currently under `root`, returning the ones that would change
+    pub fn changes(&self, root: &Path) -> Result<Vec<FileChange>> {
+        let mut changes = Vec::new();
+        for (path, contents) in self.iter() {
+            let old = match std::fs::read_to_string(root.join(path)) {
+                Ok(old) => Some(old),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+                Err(err) => return Err(err.into()),
+            };
+            if old.as_deref() != Some(contents) {
+                changes.push(FileChange {
+                    path: path.to_path_buf(),
+                    old,
+                    new: contents.to_string(),
+                });
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Writes every file under `root`, creating directories as needed and overwriting existing files
+    pub fn write_to(&self, root: &Path) -> Result<()> {
+        for (path, contents) in self.iter() {
+            let path = root.join(path);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(path, contents)?;
+        }
+        Ok(())
+    }
+}
+
+/// A file that generating or updating a project would create or change
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileChange {
+    /// The path relative to the project root
+    pub path: PathBuf,
+    /// The current contents, or `None` if the file doesn't exist yet
+    pub old: Option<String>,
+    pub new: String,
+}
+
+impl FileChange {
+    /// Renders the change as a unified diff, with `a/` and `b/` prefixed paths like `git diff`
+    pub fn unified_diff(&self) -> String {
+        let path = self.path.to_string_lossy().replace('\\', "/");
+        let old_header = match self.old {
+            Some(_) => format!("a/{}", path),
+            None => "/dev/null".into(),
+        };
+        similar::TextDiff::from_lines(self.old.as_deref().unwrap_or(""), &self.new)
+            .unified_diff()
+            .context_radius(3)
+            .header(&old_header, &format!("b/{}", path))
+            .to_string()
+    }
+}
+
+/// A proxy DLL project: the DLL it proxies, and which of its exports are forwarded or intercepted
+#[derive(Clone, Debug)]
+pub struct ProxyProject {
+    /// The cargo package name, which is also used in the proxy's message boxes
+    pub package_name: String,
+    /// The name of the proxied DLL, eg. `version.dll`
+    pub dll_name: String,
+    pub arch: Arch,
+    /// Every export the proxy provides itself, whether forwarded or intercepted
+    pub exports: BTreeSet<ExportName>,
+    /// Exports that were filtered out, which the loader forwards straight to the original DLL
+    pub forwarded_exports: BTreeSet<ExportName>,
+    /// Original names of the exports the project's hooks implement, which don't get generated forwarders
+    pub intercepted_exports: HashSet<String>,
+    /// Whether the project's `lib.rs` forwards the original DLL's `DllMain`. Projects generated by older versions don't
+    pub forwards_dll_main: bool,
+    /// Hex encoded SHA-256 of the original DLL, if known
+    pub original_sha256: Option<String>,
+    /// Where the proxy loads the original DLL from
+    pub loading: LoadingStrategy,
+    /// The proxygen version that last wrote the project's manifest, or `None` for a new project or one without a manifest
+    pub generator_version: Option<String>,
+    /// The original DLL's build for the other arch, for projects that proxy both the x86 and x64 builds.
+    /// The fields above describe the first arch, which is the one the project builds for by default
+    pub other_arch: Option<ArchExports>,
+    /// The shared hooks crate of the workspace the project is a member of, if it was generated as part of one.
+    /// Members depend on it, and leave the toolchain and build target to the workspace root
+    pub hooks_crate: Option<String>,
+    /// The `proxygen-macros` version the project's `Cargo.toml` depends on, if known.
+    /// New projects depend on the macros of the same version as the generator, see [`ProxyProject::macros_mismatch`]
+    pub macros_version: Option<String>,
+}
+
+/// One arch's build of the original DLL, and its exports
+#[derive(Clone, Debug)]
+pub struct ArchExports {
+    pub arch: Arch,
+    /// Every export the proxy provides itself on this arch
+    pub exports: BTreeSet<ExportName>,
+    /// Exports that are forwarded straight to the original DLL on this arch
+    pub forwarded_exports: BTreeSet<ExportName>,
+    /// Hex encoded SHA-256 of this arch's original DLL, if known
+    pub original_sha256: Option<String>,
+}
+
+impl ArchExports {
+    /// The exports of a DLL, none of which are forwarded yet
+    pub fn new(arch: Arch, exports: BTreeSet<ExportName>, original_sha256: Option<&str>) -> Self {
+        Self {
+            arch,
+            exports,
+            forwarded_exports: BTreeSet::new(),
+            original_sha256: original_sha256.map(String::from),
+        }
+    }
+
+    /// The rust target that builds the proxy for this arch
+    pub fn target(&self) -> &'static str {
+        rust_target(self.arch)
+    }
+
+    /// Every export of this arch's DLL, whether proxied or forwarded
+    fn names(&self) -> BTreeSet<&str> {
+        self.exports
+            .iter()
+            .chain(self.forwarded_exports.iter())
+            .map(|export| export.original.as_str())
+            .collect()
+    }
+}
+
+/// A multi-arch project's `src/export_indices.rs` or `src/orig_exports.rs`, which re-exports the module of the arch being built
+fn arch_modules(arches: &[ArchExports]) -> String {
+    let mut modules = String::from(
+        "// This file is generated by proxygen, and gets overwritten by `proxygen update` and `proxygen merge`\n\
+         // Each arch's original DLL has its own exports, so they're generated into a module per arch\n",
+    );
+    for arch in arches.iter() {
+        let target_arch = target_arch(arch.arch);
+        modules += &format!(
+            "\n#[cfg(target_arch = \"{0}\")]\nmod {0};\n#[cfg(target_arch = \"{0}\")]\npub use {0}::*;\n",
+            target_arch
+        );
+    }
+    modules
+}
+
+/// The `proxygen-macros` version a project's `Cargo.toml` depends on, eg. `0.6.0` for `proxygen-macros = "=0.6.0"`
+fn read_macros_version(project_dir: &Path) -> Option<String> {
+    let cargo_toml: toml::Table =
+        toml::from_str(&std::fs::read_to_string(project_dir.join("Cargo.toml")).ok()?).ok()?;
+    let dependency = cargo_toml.get("dependencies")?.get("proxygen-macros")?;
+    let version = match dependency {
+        toml::Value::Table(dependency) => dependency.get("version")?.as_str()?,
+        dependency => dependency.as_str()?,
+    };
+    Some(version.trim_start_matches(['=', '^', '~', ' ']).into())
+}
+
+/// `rust-toolchain.toml`. Proxies build on stable, but older versions of proxygen pinned nightly for naked functions
+pub(crate) const RUST_TOOLCHAIN: &str = "[toolchain]\nchannel = \"stable\"";
+
+/// `.cargo/config`, which makes `cargo build` build for the given arch by default
+pub(crate) fn cargo_config(arch: Arch) -> String {
+    format!("[build]\ntarget = \"{}\"", rust_target(arch))
+}
+
+fn rust_target(arch: Arch) -> &'static str {
+    match arch {
+        Arch::X86 => "i686-pc-windows-msvc",
+        Arch::X64 => "x86_64-pc-windows-msvc",
+    }
+}
+
+/// The `target_arch` cfg value that builds for the given arch
+pub fn target_arch(arch: Arch) -> &'static str {
+    match arch {
+        Arch::X86 => "x86",
+        Arch::X64 => "x86_64",
+    }
+}
+
+/// Where the proxy loads the original DLL from
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LoadingStrategy {
+    /// The proxy's own path with an underscore appended, eg. `version.dll_` next to the proxy
+    #[default]
+    Sibling,
+    /// A file name (or path) relative to the proxy's folder, eg. `version_orig.dll`
+    Relative(String),
+    /// The DLL of the same name in the system directory, for proxies of system DLLs like `version.dll` or `winmm.dll`
+    System,
+    /// The path in the given environment variable
+    Env(String),
+    /// Each strategy in turn, until one of them loads
+    Fallbacks(Vec<LoadingStrategy>),
+}
+
+impl LoadingStrategy {
+    /// A single strategy, or fallbacks when there's more than one. No strategies at all means the default
+    pub fn from_fallbacks(mut strategies: Vec<LoadingStrategy>) -> Self {
+        match strategies.len() {
+            0 => Self::default(),
+            1 => strategies.remove(0),
+            _ => Self::Fallbacks(strategies),
+        }
+    }
+
+    /// Every strategy that gets tried, in order, with any nested fallbacks flattened
+    pub fn sources(&self) -> Vec<&LoadingStrategy> {
+        match self {
+            Self::Fallbacks(strategies) => strategies
+                .iter()
+                .flat_map(|strategy| strategy.sources())
+                .collect(),
+            strategy => vec![strategy],
+        }
+    }
+
+    /// The module name that exports forwarded to the original DLL should name.
+    ///
+    /// The loader resolves forwarders by file name, so this is the first source that puts the original next to the proxy,
+    /// or `None` if none of them do.
+    pub fn forwarding_module(&self, dll_name: &str) -> Option<String> {
+        self.sources().into_iter().find_map(|source| match source {
+            Self::Sibling => Some(format!("{}_", dll_name)),
+            Self::Relative(name) if !name.contains(['/', '\\']) => Some(name.clone()),
+            _ => None,
+        })
+    }
+}
+
+impl std::str::FromStr for LoadingStrategy {
+    type Err = String;
+
+    fn from_str(strategy: &str) -> std::result::Result<Self, Self::Err> {
+        match strategy.split_once(':') {
+            None if strategy == "sibling" => Ok(Self::Sibling),
+            None if strategy == "system" => Ok(Self::System),
+            Some(("relative", name)) => {
+                if name.is_empty() || Path::new(name).has_root() || name.contains(':') {
+                    Err(format!("'{}' isn't a path relative to the proxy", name))
+                } else {
+                    Ok(Self::Relative(name.into()))
+                }
+            }
+            Some(("env", var)) if !var.is_empty() => Ok(Self::Env(var.into())),
+            _ => Err(format!(
+                "unknown strategy '{}', expected sibling, relative:NAME, system or env:VAR",
+                strategy
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for LoadingStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Sibling => write!(f, "sibling"),
+            Self::Relative(name) => write!(f, "relative:{}", name),
+            Self::System => write!(f, "system"),
+            Self::Env(var) => write!(f, "env:{}", var),
+            Self::Fallbacks(strategies) => write!(
+                f,
+                "{}",
+                strategies
+                    .iter()
+                    .map(|strategy| strategy.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+impl ProxyProject {
+    /// Creates the model for a new project
+    pub fn new(
+        package_name: impl Into<String>,
+        dll_name: impl Into<String>,
+        arch: Arch,
+        exports: BTreeSet<ExportName>,
+    ) -> Self {
+        Self {
+            package_name: package_name.into(),
+            dll_name: dll_name.into(),
+            arch,
+            exports,
+            forwarded_exports: BTreeSet::new(),
+            intercepted_exports: HashSet::new(),
+            forwards_dll_main: true,
+            original_sha256: None,
+            loading: LoadingStrategy::default(),
+            generator_version: None,
+            other_arch: None,
+            hooks_crate: None,
+            macros_version: Some(env!("CARGO_PKG_VERSION").into()),
+        }
+    }
+
+    /// Reads an existing project from disk.
+    ///
+    /// Everything but the intercepted exports comes from the `proxygen.toml` manifest, and the intercepted exports
+    /// come from the project's hooks, see [`find_hooks`](crate::hooks::find_hooks). Projects generated before the manifest existed are read back from their
+    /// generated files instead, see [`ProxyProject::read_generated_files`].
+    pub fn read(project_dir: &Path) -> Result<Self> {
+        Self::read_with_intercepted_exports(project_dir, find_intercepted_exports(project_dir)?)
+    }
+
+    /// Reads an existing project from disk like [`ProxyProject::read`], with intercepted exports that were already
+    /// found, eg. by a caller that reports duplicate hooks itself instead of failing on them
+    pub(crate) fn read_with_intercepted_exports(
+        project_dir: &Path,
+        intercepted_exports: HashSet<String>,
+    ) -> Result<Self> {
+        if !project_dir.exists() {
+            return Err(Error::ProjectNotFound(project_dir.to_path_buf()));
+        }
+        if !project_dir.join("Cargo.toml").exists() {
+            return Err(Error::NotAProject(
+                project_dir
+                    .canonicalize()
+                    .unwrap_or_else(|_| project_dir.to_path_buf()),
+            ));
+        }
+
+        let package_name = project_dir
+            .canonicalize()?
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(String::from)
+            .ok_or_else(|| Error::NotAProject(project_dir.to_path_buf()))?;
+
+        let cargo_macros_version = read_macros_version(project_dir);
+
+        // Projects generated before `DllMain` could be proxied have no way of forwarding it
+        let forwards_dll_main = std::fs::read_to_string(project_dir.join("src").join("lib.rs"))
+            .map(|lib| lib.contains("ORIG_DLL_MAIN_INDEX"))
+            .unwrap_or(false);
+
+        let manifest_path = project_dir.join(MANIFEST_FILE);
+        if !manifest_path.exists() {
+            let mut project = Self::read_generated_files(project_dir, package_name)?;
+            project.intercepted_exports = intercepted_exports;
+            project.forwards_dll_main = forwards_dll_main;
+            project.macros_version = cargo_macros_version;
+            return Ok(project);
+        }
+        let manifest = Manifest::parse(&std::fs::read_to_string(manifest_path)?)?;
+        let (exports, forwarded_exports) = manifest.exports();
+        Ok(Self {
+            package_name,
+            dll_name: manifest.dll_name().into(),
+            arch: manifest.arch()?,
+            original_sha256: manifest.sha256().map(String::from),
+            loading: manifest.loading().clone(),
+            generator_version: Some(manifest.generator_version().into()),
+            other_arch: manifest.other_arch()?,
+            hooks_crate: manifest.hooks_crate().map(String::from),
+            // `Cargo.toml` has the final say, in case the dependency was changed by hand
+            macros_version: cargo_macros_version.or(manifest.macros_version().map(String::from)),
+            exports,
+            forwarded_exports,
+            intercepted_exports,
+            forwards_dll_main,
+        })
+    }
+
+    /// Reads a project that has no `proxygen.toml` back from its generated files.
+    ///
+    /// The exports come from `src/orig_exports.rs`, and the forwarded exports from `build.rs`.
+    /// The DLL name is recovered from the `[lib]` name in `Cargo.toml`, and the arch from the target in `.cargo/config`
+    /// (x64 if it isn't set).
+    fn read_generated_files(project_dir: &Path, package_name: String) -> Result<Self> {
+        let cargo_toml = std::fs::read_to_string(project_dir.join("Cargo.toml"))?;
+        let lib_name_re = Regex::new(r#"(?s)\[lib\].*?\bname\s*=\s*"([^"]+)""#)?;
+        let dll_name = match lib_name_re.captures(&cargo_toml) {
+            Some(captures) => format!("{}.dll", &captures[1]),
+            None => format!("{}.dll", package_name),
+        };
+
+        let arch = match std::fs::read_to_string(project_dir.join(".cargo").join("config")) {
+            Ok(config) if config.contains("i686") => Arch::X86,
+            _ => Arch::X64,
+        };
+
+        // Get existing exports from src/orig_exports.rs
+        let mut exports = BTreeSet::new();
+        let exports_index_re =
+            Regex::new(r#"^load_dll_func\(Index_(.+)\s*,\s*dll_handle,\s*"(.+)"\)\s*;"#)?;
+        let exports_ordinal_re = Regex::new(
+            r#"^load_dll_func_by_ordinal\(Index_(.+)\s*,\s*dll_handle,\s*(\d+),\s*"(.+)"\)\s*;"#,
+        )?;
+        let orig_exports_path = project_dir.join("src").join("orig_exports.rs");
+        if orig_exports_path.exists() {
+            for line in std::fs::read_to_string(orig_exports_path)?.lines() {
+                if let Some(captures) = exports_index_re.captures(line.trim()) {
+                    let mut export = ExportName::new(&captures[2]);
+                    export.cleaned = captures[1].trim().to_string();
+                    exports.insert(export);
+                } else if let Some(captures) = exports_ordinal_re.captures(line.trim()) {
+                    let mut export = ExportName::new(&captures[3]);
+                    export.cleaned = captures[1].trim().to_string();
+                    export.ordinal = captures[2].parse().ok();
+                    export.noname = true;
+                    exports.insert(export);
+                }
+            }
+        }
+
+        // Get forwarded exports from build.rs
+        let mut forwarded_exports = BTreeSet::new();
+        let forwarded_re = Regex::new(r#"^\("([^"]+)"\s*,\s*"([^"]+)"\)\s*,"#)?;
+        let build_path = project_dir.join("build.rs");
+        if build_path.exists() {
+            for line in std::fs::read_to_string(build_path)?.lines() {
+                if let Some(captures) = forwarded_re.captures(line.trim()) {
+                    let mut export = ExportName::new(&captures[1]);
+                    if let Some((_, ordinal)) = captures[2].rsplit_once(".#") {
+                        export.ordinal = ordinal.parse().ok();
+                        export.noname = true;
+                    }
+                    forwarded_exports.insert(export);
+                }
+            }
+        }
+
+        Ok(Self {
+            package_name,
+            dll_name,
+            arch,
+            original_sha256: None,
+            loading: LoadingStrategy::default(),
+            generator_version: None,
+            exports,
+            forwarded_exports,
+            intercepted_exports: HashSet::new(),
+            forwards_dll_main: false,
+            other_arch: None,
+            hooks_crate: None,
+            macros_version: None,
+        })
+    }
+
+    /// Adds the given exports, replacing existing ones with the same name since the DLL knows more about them.
+    ///
+    /// Exports that are already forwarded stay forwarded, and new ones get proxied.
+    pub fn merge_exports(&mut self, exports: &BTreeSet<ExportName>) {
+        for export in exports.iter() {
+            if self.forwarded_exports.contains(export) {
+                self.forwarded_exports.replace(export.clone());
+            } else {
+                self.exports.replace(export.clone());
+            }
+        }
+    }
+
+    /// Merges in the exports of one arch's build of the original DLL, also recording its hash if it's known.
+    ///
+    /// The exports of an arch the project doesn't proxy yet make it a multi-arch project. New exports are forwarded if
+    /// the other arch already forwards them, and proxied otherwise
+    pub fn merge_arch_exports(&mut self, original: &ArchExports) {
+        if original.arch == self.arch {
+            self.merge_exports(&original.exports);
+            if original.original_sha256.is_some() {
+                self.original_sha256 = original.original_sha256.clone();
+            }
+            return;
+        }
+        let forwarded_elsewhere = self.forwarded_exports.clone();
+        let other_arch = self
+            .other_arch
+            .get_or_insert_with(|| ArchExports::new(original.arch, BTreeSet::new(), None));
+        for export in original.exports.iter() {
+            if other_arch.forwarded_exports.contains(export)
+                || !other_arch.exports.contains(export) && forwarded_elsewhere.contains(export)
+            {
+                other_arch.forwarded_exports.replace(export.clone());
+            } else {
+                other_arch.exports.replace(export.clone());
+            }
+        }
+        if original.original_sha256.is_some() {
+            other_arch.original_sha256 = original.original_sha256.clone();
+        }
+    }
+
+    /// The exports the project provides on `original`'s arch that `original` doesn't have, eg. ones removed from a new
+    /// version of the DLL. There are none for an arch the project doesn't proxy yet
+    pub fn missing_exports(&self, original: &ArchExports) -> Vec<ExportName> {
+        let Some(arch) = self
+            .arches()
+            .into_iter()
+            .find(|arch| arch.arch == original.arch)
+        else {
+            return Vec::new();
+        };
+        let names = original.names();
+        arch.exports
+            .into_iter()
+            .chain(arch.forwarded_exports)
+            .filter(|export| !names.contains(export.original.as_str()))
+            .collect()
+    }
+
+    /// Removes the exports `original` doesn't have from its arch, returning them. See [`ProxyProject::missing_exports`]
+    pub fn prune_exports(&mut self, original: &ArchExports) -> Vec<ExportName> {
+        let missing = self.missing_exports(original);
+        let (exports, forwarded_exports) = match self.other_arch.as_mut() {
+            Some(other_arch) if other_arch.arch == original.arch => {
+                (&mut other_arch.exports, &mut other_arch.forwarded_exports)
+            }
+            _ => (&mut self.exports, &mut self.forwarded_exports),
+        };
+        exports.retain(|export| !missing.contains(export));
+        forwarded_exports.retain(|export| !missing.contains(export));
+        missing
+    }
+
+    /// The proxied and forwarded exports of every arch the project proxies
+    fn split_exports_mut(&mut self) -> Vec<(&mut BTreeSet<ExportName>, &mut BTreeSet<ExportName>)> {
+        let mut splits = vec![(&mut self.exports, &mut self.forwarded_exports)];
+        if let Some(other_arch) = self.other_arch.as_mut() {
+            splits.push((&mut other_arch.exports, &mut other_arch.forwarded_exports));
+        }
+        splits
+    }
+
+    /// Every arch the project proxies, and its exports. The first one is the arch the project builds for by default
+    pub fn arches(&self) -> Vec<ArchExports> {
+        let mut arches = vec![ArchExports {
+            arch: self.arch,
+            exports: self.exports.clone(),
+            forwarded_exports: self.forwarded_exports.clone(),
+            original_sha256: self.original_sha256.clone(),
+        }];
+        arches.extend(self.other_arch.clone());
+        arches
+    }
+
+    /// Every export that's proxied on any arch, along with the arches it's proxied on if that isn't all of them
+    pub fn proxied_arches(&self) -> BTreeMap<ExportName, Option<Arch>> {
+        let arches = self.arches();
+        let mut proxied: BTreeMap<ExportName, Vec<Arch>> = BTreeMap::new();
+        for arch in arches.iter() {
+            for export in arch.exports.iter() {
+                proxied.entry(export.clone()).or_default().push(arch.arch);
+            }
+        }
+        proxied
+            .into_iter()
+            .map(|(export, proxied_on)| match proxied_on.as_slice() {
+                [arch] if arches.len() > 1 => (export, Some(*arch)),
+                _ => (export, None),
+            })
+            .collect()
+    }
+
+    /// Exports that only one arch's DLL has, along with that arch. Single-arch projects have none
+    pub fn arch_only_exports(&self) -> Vec<(ExportName, Arch)> {
+        let arches = self.arches();
+        let mut arch_only = Vec::new();
+        if let [first, second] = arches.as_slice() {
+            for (arch, other) in [(first, second), (second, first)] {
+                let other_names = other.names();
+                arch_only.extend(
+                    arch.exports
+                        .iter()
+                        .chain(arch.forwarded_exports.iter())
+                        .filter(|export| !other_names.contains(export.original.as_str()))
+                        .map(|export| (export.clone(), arch.arch)),
+                );
+            }
+        }
+        arch_only.sort_by(|a, b| a.0.cmp(&b.0));
+        arch_only
+    }
+
+    /// Splits every export between being proxied and being forwarded to the original DLL, according to `filter`.
+    ///
+    /// Intercepted exports and `DllMain` are always proxied, since the proxy has to provide them itself.
+    /// An empty filter leaves the exports as they are.
+    pub fn apply_filter(&mut self, filter: &ExportFilter) {
+        if filter.is_empty() {
+            return;
+        }
+        let intercepted_exports = self.intercepted_exports.clone();
+        for (exports, forwarded_exports) in self.split_exports_mut() {
+            let all_exports: Vec<ExportName> = std::mem::take(exports)
+                .into_iter()
+                .chain(std::mem::take(forwarded_exports))
+                .collect();
+            for export in all_exports {
+                if filter.includes(&export)
+                    || intercepted_exports.contains(&export.original)
+                    || export.original == "DllMain"
+                {
+                    exports.insert(export);
+                } else {
+                    forwarded_exports.insert(export);
+                }
+            }
+        }
+    }
+
+    /// Finds the exports that this project's proxy isn't able to provide, on any of its arches
+    pub fn find_unprovidable_exports(&self) -> Vec<UnprovidableExport> {
+        // Every arch shares `src/proxied_exports.rs`, so identifiers can't collide across arches either
+        let arches = self.arches();
+        let exports: BTreeSet<ExportName> = arches
+            .iter()
+            .flat_map(|arch| arch.exports.iter().cloned())
+            .collect();
+        let forwarded_exports: BTreeSet<ExportName> = arches
+            .iter()
+            .flat_map(|arch| arch.forwarded_exports.iter().cloned())
+            .collect();
+        let mut unprovidable = find_unprovidable_exports(&exports);
+        // Forwarded exports don't get identifiers, but still can't use the reserved prefix, and are named in module.def
+        for export in forwarded_exports.iter() {
+            if export.original.starts_with(RESERVED_SYMBOL_PREFIX) {
+                unprovidable.push(UnprovidableExport {
+                    export: export.clone(),
+                    reason: UnprovidableReason::Reserved,
+                });
+            } else if export.original.contains('"') {
+                unprovidable.push(UnprovidableExport {
+                    export: export.clone(),
+                    reason: UnprovidableReason::Unquotable,
+                });
+            }
+        }
+        if !self.forwards_dll_main {
+            if let Some(dll_main) = exports.iter().find(|export| export.original == "DllMain") {
+                unprovidable.push(UnprovidableExport {
+                    export: dll_main.clone(),
+                    reason: UnprovidableReason::DllMainUnsupported,
+                });
+            }
+        }
+        // Data exports and forwarded exports are resolved by the loader, which can only find the original by file name
+        if self.loading.forwarding_module(&self.dll_name).is_none() {
+            let unforwardable: Vec<ExportName> = forwarded_exports
+                .iter()
+                .chain(
+                    exports
+                        .iter()
+                        .filter(|export| export.kind == ExportKind::Data),
+                )
+                .filter(|export| {
+                    !unprovidable
+                        .iter()
+                        .any(|unprovidable| unprovidable.export == **export)
+                })
+                .cloned()
+                .collect();
+            unprovidable.extend(unforwardable.into_iter().map(|export| UnprovidableExport {
+                export,
+                reason: UnprovidableReason::Unforwardable(self.loading.to_string()),
+            }));
+        }
+        unprovidable
+    }
+
+    /// Leaves out the exports that the proxy can't provide, returning them.
+    ///
+    /// Unless `allow_unprovided` is set, this fails with [`Error::UnprovidableExports`] instead.
+    pub fn exclude_unprovidable_exports(
+        &mut self,
+        allow_unprovided: bool,
+    ) -> Result<Vec<UnprovidableExport>> {
+        let unprovidable = self.find_unprovidable_exports();
+        if unprovidable.is_empty() {
+            return Ok(unprovidable);
+        }
+        if !allow_unprovided {
+            return Err(Error::UnprovidableExports(unprovidable));
+        }
+        let is_providable = |export: &ExportName| {
+            !unprovidable
+                .iter()
+                .any(|unprovidable| unprovidable.export == *export)
+        };
+        for (exports, forwarded_exports) in self.split_exports_mut() {
+            exports.retain(is_providable);
+            forwarded_exports.retain(is_providable);
+        }
+        Ok(unprovidable)
+    }
+
+    /// The name of the `cdylib` target, ie. the DLL name without its extension.
+    ///
+    /// Hyphens aren't allowed in crate names, so they're replaced with underscores
+    pub fn lib_name(&self) -> String {
+        self.dll_name.replace(".dll", "").replace('-', "_")
+    }
+
+    /// The project's `proxygen-macros` version, if it isn't the same as this proxygen's.
+    ///
+    /// The generated files and the macros share an ABI (`ORIG_FUNCS_PTR`, `wait_dll_proxy_init` and the export indices),
+    /// so files generated by one version may not work with the macros of another.
+    pub fn macros_mismatch(&self) -> Option<&str> {
+        self.macros_version
+            .as_deref()
+            .filter(|version| *version != env!("CARGO_PKG_VERSION"))
+    }
+
+    /// The rust target the project builds for by default
+    pub fn target(&self) -> &'static str {
+        rust_target(self.arch)
+    }
+
+    /// The module name that the exports forwarded to the original DLL name. Data exports are always forwarded
+    fn forwarding_module(&self) -> Result<String> {
+        match self.loading.forwarding_module(&self.dll_name) {
+            Some(module) => Ok(module),
+            None if self.arches().iter().all(|arch| {
+                arch.forwarded_exports.is_empty()
+                    && arch
+                        .exports
+                        .iter()
+                        .all(|export| export.kind != ExportKind::Data)
+            }) =>
+            {
+                Ok(format!("{}_", self.dll_name))
+            }
+            None => Err(Error::UnforwardableExports(self.loading.to_string())),
+        }
+    }
+
+    /// Generates every file of a new project
+    pub fn generate(&self, templates: &ProxyTemplates) -> Result<ProjectFiles> {
+        let mut files = self.generate_exports(templates)?;
+        files.insert(
+            "Cargo.toml",
+            templates.get_cargo_toml(
+                &self.package_name,
+                self.lib_name(),
+                self.hooks_crate.as_deref(),
+            )?,
+        );
+        if self.hooks_crate.is_none() {
+            files.insert("rust-toolchain.toml", RUST_TOOLCHAIN);
+            files.insert(Path::new(".cargo").join("config"), cargo_config(self.arch));
+        }
+        files.insert(
+            Path::new("src").join("intercepted_exports.rs"),
+            templates.get_intercepted_exports()?,
+        );
+        files.insert(
+            Path::new("src").join("lib.rs"),
+            templates.get_lib(&self.package_name, &self.dll_name, &self.loading)?,
+        );
+        Ok(files)
+    }
+
+    /// Generates just the files that are regenerated whenever the project's exports change
+    pub fn generate_exports(&self, templates: &ProxyTemplates) -> Result<ProjectFiles> {
+        let forwarding_module = self.forwarding_module()?;
+        let mut files = ProjectFiles::new();
+        files.insert(MANIFEST_FILE, Manifest::new(self).render()?);
+        let arches = self.arches();
+        if let [arch] = arches.as_slice() {
+            files.insert(
+                Path::new("src").join("export_indices.rs"),
+                templates.get_export_indices(&arch.exports)?,
+            );
+            files.insert(
+                Path::new("src").join("orig_exports.rs"),
+                templates.get_orig_exports(&arch.exports)?,
+            );
+            files.insert(
+                "module.def",
+                templates.get_module_def(
+                    &self.dll_name,
+                    &arch.exports,
+                    &arch.forwarded_exports,
+                    &forwarding_module,
+                )?,
+            );
+        } else {
+            // Each arch gets its own export indices, original exports and module definition file, and
+            // `src/export_indices.rs` and `src/orig_exports.rs` just pick the one for the arch being built
+            for module in ["export_indices", "orig_exports"] {
+                files.insert(
+                    Path::new("src").join(format!("{}.rs", module)),
+                    arch_modules(&arches),
+                );
+            }
+            for arch in arches.iter() {
+                let target_arch = target_arch(arch.arch);
+                files.insert(
+                    Path::new("src")
+                        .join("export_indices")
+                        .join(format!("{}.rs", target_arch)),
+                    templates.get_export_indices(&arch.exports)?,
+                );
+                files.insert(
+                    Path::new("src")
+                        .join("orig_exports")
+                        .join(format!("{}.rs", target_arch)),
+                    templates.get_orig_exports(&arch.exports)?,
+                );
+                files.insert(
+                    format!("module.{}.def", target_arch),
+                    templates.get_module_def(
+                        &self.dll_name,
+                        &arch.exports,
+                        &arch.forwarded_exports,
+                        &forwarding_module,
+                    )?,
+                );
+            }
+        }
+        files.insert(
+            Path::new("src").join("proxied_exports.rs"),
+            templates.get_proxied_exports(&self.proxied_arches(), &self.intercepted_exports)?,
+        );
+        files.insert("build.rs", templates.get_build_rs()?);
+        Ok(files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    fn names(exports: &BTreeSet<ExportName>) -> Vec<&str> {
+        exports
+            .iter()
+            .map(|export| export.original.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn parses_loading_strategies() {
+        let parse = |strategy: &str| strategy.parse::<LoadingStrategy>();
+        assert_eq!(parse("sibling"), Ok(LoadingStrategy::Sibling));
+        assert_eq!(parse("system"), Ok(LoadingStrategy::System));
+        assert_eq!(
+            parse("relative:originals/foo.dll"),
+            Ok(LoadingStrategy::Relative("originals/foo.dll".into()))
+        );
+        assert_eq!(
+            parse("env:FOO_DLL"),
+            Ok(LoadingStrategy::Env("FOO_DLL".into()))
+        );
+        for invalid in [
+            "",
+            "siblings",
+            "relative:",
+            "relative:/foo.dll",
+            "relative:C:foo.dll",
+            "env:",
+            "system:foo",
+        ] {
+            assert!(parse(invalid).is_err(), "{} should be invalid", invalid);
+        }
+        // Strategies are written back the way they're parsed
+        let fallbacks = LoadingStrategy::from_fallbacks(vec![
+            parse("env:FOO_DLL").unwrap(),
+            parse("relative:foo.dll").unwrap(),
+        ]);
+        assert_eq!(fallbacks.to_string(), "env:FOO_DLL, relative:foo.dll");
+        assert_eq!(
+            LoadingStrategy::from_fallbacks(Vec::new()),
+            LoadingStrategy::Sibling
+        );
+        assert_eq!(
+            LoadingStrategy::from_fallbacks(vec![LoadingStrategy::System]),
+            LoadingStrategy::System
+        );
+    }
+
+    #[test]
+    fn forwards_to_the_first_original_next_to_the_proxy() {
+        let forwarding_module = |strategies: Vec<LoadingStrategy>| {
+            LoadingStrategy::from_fallbacks(strategies).forwarding_module("foo.dll")
+        };
+        assert_eq!(forwarding_module(vec![]), Some("foo.dll_".into()));
+        assert_eq!(
+            forwarding_module(vec![
+                LoadingStrategy::Env("FOO_DLL".into()),
+                LoadingStrategy::Relative("originals/foo.dll".into()),
+                LoadingStrategy::Relative("foo_orig.dll".into()),
+                LoadingStrategy::Sibling,
+            ]),
+            Some("foo_orig.dll".into())
+        );
+        // The loader only looks for forwarded modules by name, so none of these can be forwarded to
+        assert_eq!(
+            forwarding_module(vec![
+                LoadingStrategy::System,
+                LoadingStrategy::Env("FOO_DLL".into()),
+                LoadingStrategy::Relative("originals\\foo.dll".into()),
+            ]),
+            None
+        );
+    }
+
+    #[test]
+    fn reads_projects_from_their_manifest() {
+        let dir = TempDir::new("reads_projects_from_their_manifest");
+        let mut project = ProxyProject::new(
+            "proxy",
+            "foo.dll",
+            Arch::X64,
+            BTreeSet::from([ExportName::new("Foo")]),
+        );
+        project.forwarded_exports.insert(ExportName::new("Bar"));
+        project.loading = LoadingStrategy::System;
+        dir.write(MANIFEST_FILE, &Manifest::new(&project).render().unwrap());
+        dir.write(
+            "Cargo.toml",
+            "[package]\nname = \"proxy\"\n\n[dependencies]\nproxygen-macros = \"=0.5.1\"\n",
+        );
+        dir.write(
+            "src/lib.rs",
+            "mod intercepted_exports;\nconst ORIG_DLL_MAIN_INDEX: usize = 0;\n",
+        );
+        dir.write(
+            "src/intercepted_exports.rs",
+            "#[pre_hook(sig = \"unknown\")]\n#[export_name = \"Foo\"]\npub extern \"C\" fn foo_hook() {}\n",
+        );
+
+        let read = ProxyProject::read(dir.path()).unwrap();
+        // The package is named after the project's folder
+        assert_eq!(
+            read.package_name,
+            dir.path().file_name().unwrap().to_str().unwrap()
+        );
+        assert_eq!(read.dll_name, "foo.dll");
+        assert_eq!(read.arch, Arch::X64);
+        assert_eq!(read.loading, LoadingStrategy::System);
+        assert_eq!(names(&read.exports), ["Foo"]);
+        assert_eq!(names(&read.forwarded_exports), ["Bar"]);
+        assert_eq!(read.intercepted_exports, HashSet::from(["Foo".to_string()]));
+        assert!(read.forwards_dll_main);
+        assert_eq!(
+            read.generator_version.as_deref(),
+            Some(env!("CARGO_PKG_VERSION"))
+        );
+        // `Cargo.toml` wins over the manifest
+        assert_eq!(read.macros_version.as_deref(), Some("0.5.1"));
+    }
+
+    #[test]
+    fn refuses_to_read_non_projects() {
+        let dir = TempDir::new("refuses_to_read_non_projects");
+        assert!(matches!(
+            ProxyProject::read(&dir.path().join("missing")),
+            Err(Error::ProjectNotFound(_))
+        ));
+        assert!(matches!(
+            ProxyProject::read(dir.path()),
+            Err(Error::NotAProject(_))
+        ));
+    }
+
+    #[test]
+    fn merged_exports_keep_their_split() {
+        let mut project = ProxyProject::new(
+            "proxy",
+            "foo.dll",
+            Arch::X64,
+            BTreeSet::from([ExportName::new("Foo")]),
+        );
+        project.forwarded_exports.insert(ExportName::new("Bar"));
+        let bar = ExportName {
+            ordinal: Some(2),
+            ..ExportName::new("Bar")
+        };
+        project.merge_exports(&BTreeSet::from([bar, ExportName::new("Baz")]));
+        assert_eq!(names(&project.exports), ["Baz", "Foo"]);
+        assert_eq!(names(&project.forwarded_exports), ["Bar"]);
+        // The DLL knows more about the export than the project did
+        assert_eq!(project.forwarded_exports.first().unwrap().ordinal, Some(2));
+    }
+
+    #[test]
+    fn prunes_exports_the_original_lost() {
+        let exports = BTreeSet::from([ExportName::new("Foo"), ExportName::new("Gone")]);
+        let mut project = ProxyProject::new("proxy", "foo.dll", Arch::X64, exports);
+        project
+            .forwarded_exports
+            .insert(ExportName::new("AlsoGone"));
+        project.other_arch = Some(ArchExports::new(
+            Arch::X86,
+            BTreeSet::from([ExportName::new("Foo"), ExportName::new("Gone")]),
+            None,
+        ));
+        let original = ArchExports::new(Arch::X64, BTreeSet::from([ExportName::new("Foo")]), None);
+
+        let missing = project.missing_exports(&original);
+        let missing: Vec<&str> = missing
+            .iter()
+            .map(|export| export.original.as_str())
+            .collect();
+        assert_eq!(missing, ["Gone", "AlsoGone"]);
+        assert_eq!(project.prune_exports(&original).len(), 2);
+        assert_eq!(names(&project.exports), ["Foo"]);
+        assert!(project.forwarded_exports.is_empty());
+        // The other arch's build is pruned separately
+        assert_eq!(
+            names(&project.other_arch.as_ref().unwrap().exports),
+            ["Foo", "Gone"]
+        );
+        let x86 = ArchExports::new(Arch::X86, BTreeSet::from([ExportName::new("Foo")]), None);
+        assert_eq!(project.prune_exports(&x86).len(), 1);
+        assert_eq!(
+            names(&project.other_arch.as_ref().unwrap().exports),
+            ["Foo"]
+        );
+    }
+
+    #[test]
+    fn reads_pinned_macros_version() {
+        let project = TempDir::new("reads_pinned_macros_version");
+        project.write(
+            "Cargo.toml",
+            "[package]\nname = \"proxy\"\n\n[dependencies]\nproxygen-macros = \"=0.5.1\"\n",
+        );
+        assert_eq!(
+            read_macros_version(project.path()).as_deref(),
+            Some("0.5.1")
+        );
+
+        project.write(
+            "Cargo.toml",
+            "[dependencies]\nproxygen-macros = { version = \"^0.4\", path = \"../macros\" }\n",
+        );
+        assert_eq!(read_macros_version(project.path()).as_deref(), Some("0.4"));
+
+        project.write("Cargo.toml", "[dependencies]\n");
+        assert_eq!(read_macros_version(project.path()), None);
+    }
+
+    #[test]
+    fn older_macros_are_a_mismatch() {
+        let mut project = ProxyProject::new("proxy", "foo.dll", Arch::X64, BTreeSet::new());
+        assert_eq!(project.macros_mismatch(), None);
+        // Projects generated by 0.5.1 use the macros ABI from before the symbols were namespaced
+        project.macros_version = Some("0.5.1".into());
+        assert_eq!(project.macros_mismatch(), Some("0.5.1"));
+    }
+
+    #[test]
+    fn unforwardable_exports_are_unprovidable() {
+        let counter = ExportName {
+            kind: ExportKind::Data,
+            ..ExportName::new("Counter")
+        };
+        let exports = BTreeSet::from([ExportName::new("Foo"), counter]);
+        let mut project = ProxyProject::new("proxy", "foo.dll", Arch::X64, exports);
+        project.forwarded_exports.insert(ExportName::new("Bar"));
+        assert!(project.find_unprovidable_exports().is_empty());
+
+        // The loader can't find a DLL loaded from the system directory by file name
+        project.loading = LoadingStrategy::System;
+        let unprovidable = project.find_unprovidable_exports();
+        let unprovidable: Vec<&str> = unprovidable
+            .iter()
+            .map(|unprovidable| unprovidable.export.original.as_str())
+            .collect();
+        assert_eq!(unprovidable, ["Bar", "Counter"]);
+        assert!(matches!(
+            project.exclude_unprovidable_exports(false),
+            Err(Error::UnprovidableExports(_))
+        ));
+        assert_eq!(project.exclude_unprovidable_exports(true).unwrap().len(), 2);
+        assert!(project.forwarded_exports.is_empty());
+        assert_eq!(project.exports, BTreeSet::from([ExportName::new("Foo")]));
+        assert!(project
+            .generate_exports(&ProxyTemplates::new().unwrap())
+            .is_ok());
+    }
+}
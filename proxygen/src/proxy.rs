@@ -1,14 +1,25 @@
-use anyhow::{Ok, Result};
 use exe::Arch;
+use quote::ToTokens;
 use regex::Regex;
 use std::{
-    collections::{BTreeSet, HashSet},
-    path::PathBuf,
+    collections::{BTreeMap, BTreeSet, HashSet},
+    path::{Path, PathBuf},
     process::Command,
 };
+use syn::spanned::Spanned;
 use tera::{Context, Tera};
 
-use crate::exports::ExportName;
+use crate::{
+    exports::{ExportKind, ExportName, UnprovidableExport, UnprovidableReason},
+    filter::ExportFilter,
+    hooks::find_intercepted_exports,
+    manifest::MANIFEST_FILE,
+    project::{
+        cargo_config, target_arch, ArchExports, FileChange, LoadingStrategy, ProjectFiles,
+        ProxyProject, RUST_TOOLCHAIN,
+    },
+    Error, Result,
+};
 
 const CARGO_TEMPLATE: &str = include_str!("templates/Cargo");
 const EXPORT_INDICES_TEMPLATE: &str = include_str!("templates/export_indices");
@@ -17,33 +28,140 @@ const LIB_TEMPLATE: &str = include_str!("templates/lib");
 const ORIG_EXPORTS_TEMPLATE: &str = include_str!("templates/orig_exports");
 const PROXIED_EXPORTS_TEMPLATE: &str = include_str!("templates/proxied_exports");
 const MODULE_DEF_TEMPLATE: &str = include_str!("templates/module_def");
+const BUILD_TEMPLATE: &str = include_str!("templates/build");
+
+/// `src/lib.rs` as earlier releases generated it, keyed by the release. See [`ProxyTemplates::get_known_libs`]
+const LEGACY_LIB_TEMPLATES: &[(&str, &str)] =
+    &[("0.5.1", include_str!("templates/legacy/lib-0.5.1"))];
+
+/// Every built in template, keyed by its name (which is also its file name in a template pack)
+const BUILTIN_TEMPLATES: &[(&str, &str)] = &[
+    ("Cargo.toml", CARGO_TEMPLATE),
+    ("export_indices.rs", EXPORT_INDICES_TEMPLATE),
+    ("intercepted_exports.rs", INTERCEPTED_EXPORTS_TEMPLATE),
+    ("lib.rs", LIB_TEMPLATE),
+    ("orig_exports.rs", ORIG_EXPORTS_TEMPLATE),
+    ("proxied_exports.rs", PROXIED_EXPORTS_TEMPLATE),
+    ("module.def", MODULE_DEF_TEMPLATE),
+    ("build.rs", BUILD_TEMPLATE),
+];
+
+/// The version of the template variables the built in templates are rendered with.
+///
+/// This is bumped whenever a template's variables change, or the code the templates generate has to change with the
+/// rest of proxygen (eg. the crates it depends on), so packs exported from older versions aren't silently rendered with
+/// variables they don't know about, or into files that no longer build.
+pub const TEMPLATE_PACK_VERSION: u32 = 8;
 
-struct ProxyTemplates {
+/// The file describing a template pack
+pub const TEMPLATE_PACK_MANIFEST: &str = "template-pack.toml";
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct TemplatePackManifest {
+    /// The [`TEMPLATE_PACK_VERSION`] the pack was written for
+    version: u32,
+    /// The proxygen version the pack was exported from, for reference
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    proxygen: Option<String>,
+}
+
+/// The templates every file of a generated project is rendered from
+pub struct ProxyTemplates {
     tera: Tera,
 }
 
 impl ProxyTemplates {
+    /// Loads the built in templates
     pub fn new() -> Result<Self> {
-        let mut tera = Tera::new("templates/**/*")?;
-        tera.add_raw_template("Cargo.toml", CARGO_TEMPLATE)?;
-        tera.add_raw_template("export_indices.rs", EXPORT_INDICES_TEMPLATE)?;
-        tera.add_raw_template("intercepted_exports.rs", INTERCEPTED_EXPORTS_TEMPLATE)?;
-        tera.add_raw_template("lib.rs", LIB_TEMPLATE)?;
-        tera.add_raw_template("orig_exports.rs", ORIG_EXPORTS_TEMPLATE)?;
-        tera.add_raw_template("proxied_exports.rs", PROXIED_EXPORTS_TEMPLATE)?;
-        tera.add_raw_template("module.def", MODULE_DEF_TEMPLATE)?;
+        let mut tera = Tera::default();
+        for (name, template) in BUILTIN_TEMPLATES {
+            tera.add_raw_template(name, template)?;
+        }
+        for (version, template) in LEGACY_LIB_TEMPLATES {
+            tera.add_raw_template(&legacy_lib_template(version), template)?;
+        }
 
         Ok(Self { tera })
     }
 
+    /// Loads the built in templates, overriding them with any found in the template pack at `dir`.
+    ///
+    /// The pack needs a `template-pack.toml` with the [`TEMPLATE_PACK_VERSION`] it was written for,
+    /// and every other file in it has to be named after one of the built in templates.
+    pub fn from_dir(dir: &Path) -> Result<Self> {
+        let manifest_path = dir.join(TEMPLATE_PACK_MANIFEST);
+        let manifest = std::fs::read_to_string(&manifest_path).map_err(|err| {
+            Error::InvalidTemplatePack {
+                path: dir.to_path_buf(),
+                message: format!("failed to read {}: {}", TEMPLATE_PACK_MANIFEST, err),
+            }
+        })?;
+        let manifest: TemplatePackManifest =
+            toml::from_str(&manifest).map_err(|err| Error::InvalidTemplatePack {
+                path: dir.to_path_buf(),
+                message: format!("failed to parse {}: {}", TEMPLATE_PACK_MANIFEST, err),
+            })?;
+        if manifest.version != TEMPLATE_PACK_VERSION {
+            return Err(Error::IncompatibleTemplatePack {
+                path: dir.to_path_buf(),
+                version: manifest.version,
+            });
+        }
+
+        let mut templates = Self::new()?;
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            let name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            if name == TEMPLATE_PACK_MANIFEST {
+                continue;
+            }
+            if !BUILTIN_TEMPLATES.iter().any(|(builtin, _)| *builtin == name) {
+                return Err(Error::UnknownTemplate(path));
+            }
+            let template = std::fs::read_to_string(&path)?;
+            templates.tera.add_raw_template(&name, &template)?;
+        }
+        Ok(templates)
+    }
+
+    /// The built in templates as a template pack, ready to be customized
+    pub fn export_builtin() -> Result<ProjectFiles> {
+        let mut files = ProjectFiles::new();
+        let manifest = TemplatePackManifest {
+            version: TEMPLATE_PACK_VERSION,
+            proxygen: Some(env!("CARGO_PKG_VERSION").into()),
+        };
+        let manifest = toml::to_string(&manifest).map_err(|err| Error::InvalidTemplatePack {
+            path: TEMPLATE_PACK_MANIFEST.into(),
+            message: err.to_string(),
+        })?;
+        files.insert(
+            TEMPLATE_PACK_MANIFEST,
+            format!(
+                "# A proxygen template pack. `version` is the version of the templates' variables it was written for\n{}",
+                manifest
+            ),
+        );
+        for (name, template) in BUILTIN_TEMPLATES {
+            files.insert(*name, *template);
+        }
+        Ok(files)
+    }
+
     pub fn get_cargo_toml(
         &self,
         package_name: impl Into<String>,
         target_dll_name: impl Into<String>,
+        hooks_crate: Option<&str>,
     ) -> Result<String> {
         let mut ctx = Context::new();
         ctx.insert("package_name", &package_name.into());
         ctx.insert("target_dll_name", &target_dll_name.into());
+        ctx.insert("hooks_crate", &hooks_crate);
+        ctx.insert("proxygen_version", env!("CARGO_PKG_VERSION"));
         Ok(self.tera.render("Cargo.toml", &ctx)?)
     }
 
@@ -58,18 +176,56 @@ impl ProxyTemplates {
             .fold(String::new(), |acc, x| acc + "\n" + &x)
             .trim_start()
             .into();
+        let export_names: String = exports
+            .iter()
+            .map(|export_name| {
+                format!(
+                    "    ({:?}, Index_{}),",
+                    export_name.original, export_name.cleaned
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let dll_main_index = match exports.iter().find(|export| export.original == "DllMain") {
+            Some(export) => format!("Some(Index_{})", export.cleaned),
+            None => "None".into(),
+        };
         ctx.insert("export_indices", &export_indices);
+        ctx.insert("export_names", &export_names);
         ctx.insert("total_exports", &exports.len());
+        ctx.insert("dll_main_index", &dll_main_index);
         Ok(self.tera.render("export_indices.rs", &ctx)?)
     }
 
-    pub fn get_lib(&self, package_name: impl Into<String>) -> Result<String> {
-        let mut ctx = Context::new();
-        let package_name: String = package_name.into();
-        ctx.insert("package_name", &package_name.to_uppercase());
+    pub fn get_lib(
+        &self,
+        package_name: impl Into<String>,
+        dll_name: impl Into<String>,
+        loading: &LoadingStrategy,
+    ) -> Result<String> {
+        let ctx = lib_context(package_name, dll_name, loading);
         Ok(self.tera.render("lib.rs", &ctx)?)
     }
 
+    /// Renders `src/lib.rs` the way these templates, the built in ones and those of earlier releases generate it, to
+    /// tell whether a project's copy was changed by hand
+    pub fn get_known_libs(
+        &self,
+        package_name: &str,
+        dll_name: &str,
+        loading: &LoadingStrategy,
+    ) -> Result<Vec<String>> {
+        let ctx = lib_context(package_name, dll_name, loading);
+        let mut known = vec![
+            self.tera.render("lib.rs", &ctx)?,
+            Tera::one_off(LIB_TEMPLATE, &ctx, false)?,
+        ];
+        for (version, _) in LEGACY_LIB_TEMPLATES {
+            known.push(self.tera.render(&legacy_lib_template(version), &ctx)?);
+        }
+        Ok(known)
+    }
+
     pub fn get_intercepted_exports(&self) -> Result<String> {
         let ctx = Context::new();
         Ok(self.tera.render("intercepted_exports.rs", &ctx)?)
@@ -79,11 +235,15 @@ impl ProxyTemplates {
         let mut ctx = Context::new();
         let dll_exports: String = exports
             .iter()
-            .map(|export_name| {
-                format!(
-                    r#"load_dll_func(Index_{}, dll_handle, "{}");"#,
+            .map(|export_name| match export_name.ordinal {
+                Some(ordinal) if export_name.noname => format!(
+                    "load_dll_func_by_ordinal(Index_{}, dll_handle, {}, {:?});",
+                    export_name.cleaned, ordinal, export_name.original
+                ),
+                _ => format!(
+                    "load_dll_func(Index_{}, dll_handle, {:?});",
                     export_name.cleaned, export_name.original
-                )
+                ),
             })
             .fold(String::new(), |acc, x| acc + "\n    " + &x)
             .trim_start()
@@ -92,19 +252,29 @@ impl ProxyTemplates {
         Ok(self.tera.render("orig_exports.rs", &ctx)?)
     }
 
+    /// `exports` are every proxied export, along with the only arch it's proxied on, if any
     pub fn get_proxied_exports(
         &self,
-        exports: &BTreeSet<ExportName>,
+        exports: &BTreeMap<ExportName, Option<Arch>>,
         exclusions: &HashSet<String>,
     ) -> Result<String> {
         let mut ctx = Context::new();
         let proxy_exports: String = exports
-            .into_iter()
-            .filter(|x| !exclusions.contains(&x.cleaned))
-            .map(|export_name| {
+            .iter()
+            // lib.rs' own `DllMain` takes care of forwarding to the original's, and data exports are forwarded by `module.def`
+            .filter(|(x, _)| {
+                !exclusions.contains(&x.original)
+                    && x.original != "DllMain"
+                    && x.kind != ExportKind::Data
+            })
+            .map(|(export_name, arch)| {
+                let cfg = match arch {
+                    Some(arch) => format!("#[cfg(target_arch = \"{}\")]\n", target_arch(*arch)),
+                    None => String::new(),
+                };
                 format!(
-                    "#[forward]\n#[export_name=\"{1}\"]\npub extern \"C\" fn {0}() {{}}\n",
-                    export_name.cleaned, export_name.original
+                    "{2}#[forward]\n#[export_name=\"{1}\"]\npub extern \"C\" fn {0}() {{}}\n",
+                    export_name.cleaned, export_name.original, cfg
                 )
             })
             .fold(String::new(), |acc, x| acc + "\n" + &x)
@@ -113,207 +283,1071 @@ impl ProxyTemplates {
         ctx.insert("proxy_exports", &proxy_exports);
         Ok(self.tera.render("proxied_exports.rs", &ctx)?)
     }
+
+    /// Renders `module.def`, listing every export with its ordinal and flags.
+    /// Forwarded exports point at `forwarding_module`, the original DLL's file name as the loader should find it, eg. `version.dll_`.
+    /// So do data exports, since a thunk can't stand in for a variable
+    pub fn get_module_def(
+        &self,
+        dll_name: impl Into<String>,
+        exports: &BTreeSet<ExportName>,
+        forwarded_exports: &BTreeSet<ExportName>,
+        forwarding_module: impl Into<String>,
+    ) -> Result<String> {
+        let mut ctx = Context::new();
+        let forwarding_module: String = forwarding_module.into();
+        let mut lines: Vec<(&str, String)> = exports
+            .iter()
+            .map(|export_name| {
+                let forwarding_module =
+                    (export_name.kind == ExportKind::Data).then_some(forwarding_module.as_str());
+                Ok((
+                    export_name.original.as_str(),
+                    module_def_line(export_name, forwarding_module)?,
+                ))
+            })
+            .chain(forwarded_exports.iter().map(|export_name| {
+                Ok((
+                    export_name.original.as_str(),
+                    module_def_line(export_name, Some(&forwarding_module))?,
+                ))
+            }))
+            .collect::<Result<_>>()?;
+        lines.sort();
+        let exports: String = lines
+            .into_iter()
+            .map(|(_, line)| line)
+            .fold(String::new(), |acc, x| acc + "\n    " + &x)
+            .trim_start()
+            .into();
+        ctx.insert("dll_name", &dll_name.into());
+        ctx.insert("exports", &exports);
+        Ok(self.tera.render("module.def", &ctx)?)
+    }
+
+    /// Renders the `build.rs` that passes `module.def` to the linker
+    pub fn get_build_rs(&self) -> Result<String> {
+        let ctx = Context::new();
+        Ok(self.tera.render("build.rs", &ctx)?)
+    }
 }
 
-/// Creates a new proxy DLL rust project
-pub fn create_proxy_project(
-    exports: &BTreeSet<ExportName>,
+/// The variables `src/lib.rs` is rendered with
+fn lib_context(
+    package_name: impl Into<String>,
     dll_name: impl Into<String>,
-    out_dir: &PathBuf,
-    arch: Arch,
-) -> Result<()> {
+    loading: &LoadingStrategy,
+) -> Context {
+    let mut ctx = Context::new();
+    let package_name: String = package_name.into();
+    ctx.insert("package_name", &package_name.to_uppercase());
     let dll_name: String = dll_name.into();
-    if out_dir.exists() {
-        return Err(anyhow::anyhow!(
-            "Folder {} already exists. Aborting",
-            out_dir.to_string_lossy()
-        ));
+    ctx.insert("dll_name", &format!("{:?}", dll_name));
+    // Each source's kind, and its value as a rust string literal
+    let original_sources: Vec<BTreeMap<&str, String>> = loading
+        .sources()
+        .into_iter()
+        .map(|source| {
+            let (kind, value) = match source {
+                LoadingStrategy::Sibling => ("sibling", ""),
+                LoadingStrategy::Relative(name) => ("relative", name.as_str()),
+                LoadingStrategy::System => ("system", ""),
+                LoadingStrategy::Env(var) => ("env", var.as_str()),
+                LoadingStrategy::Fallbacks(_) => unreachable!("sources are flattened"),
+            };
+            BTreeMap::from([("kind", kind.into()), ("value", format!("{:?}", value))])
+        })
+        .collect();
+    ctx.insert("original_sources", &original_sources);
+    ctx
+}
+
+/// The name an earlier release's `src/lib.rs` template is registered under
+fn legacy_lib_template(version: &str) -> String {
+    format!("legacy/lib-{}.rs", version)
+}
+
+/// Exports the linker expects to be left out of the import library, since they're only ever looked up at runtime
+const PRIVATE_EXPORTS: &[&str] = &[
+    "DllMain",
+    "DllCanUnloadNow",
+    "DllGetClassObject",
+    "DllGetActivationFactory",
+    "DllInstall",
+    "DllRegisterServer",
+    "DllUnregisterServer",
+];
+
+/// A `module.def` export line: `"Name" [= "target"] [@ordinal] [NONAME] [DATA] [PRIVATE]`.
+///
+/// Names are quoted, since they can contain `?` and `@`. `module.def` has no way to escape a `"` though, so names
+/// containing one are refused. Forwarded exports point at the same export of `forwarding_module`
+fn module_def_line(export_name: &ExportName, forwarding_module: Option<&str>) -> Result<String> {
+    if export_name.original.contains('"') {
+        return Err(Error::UnprovidableExports(vec![UnprovidableExport {
+            export: export_name.clone(),
+            reason: UnprovidableReason::Unquotable,
+        }]));
     }
-    let status = Command::new("cargo")
-        .arg("new")
-        .arg("--lib")
-        .arg(out_dir)
-        .status()?;
-    if !status.success() {
-        return Err(anyhow::anyhow!(
-            "Failed to create new proxy project at {}. Exit code: {}",
-            out_dir.to_string_lossy(),
-            match status.code() {
-                Some(code) => format!("{code}"),
-                None => "None".into(),
+    let mut line = format!("\"{}\"", export_name.original);
+    if let Some(forwarding_module) = forwarding_module {
+        match export_name.ordinal {
+            Some(ordinal) if export_name.noname => {
+                line += &format!(" = \"{}.#{}\"", forwarding_module, ordinal)
             }
-        ));
+            _ => line += &format!(" = \"{}.{}\"", forwarding_module, export_name.original),
+        }
+    }
+    if let Some(ordinal) = export_name.ordinal {
+        line += &format!(" @{}", ordinal);
+        if export_name.noname {
+            line += " NONAME";
+        }
+    }
+    if export_name.kind == ExportKind::Data {
+        line += " DATA";
     }
+    if PRIVATE_EXPORTS.contains(&export_name.original.as_str()) {
+        line += " PRIVATE";
+    }
+    Ok(line)
+}
 
-    let package_name = out_dir
-        .file_name()
-        .expect("Invalid output directory name")
-        .to_str()
-        .expect("Unable to detect package name");
+/// The outcome of creating or updating a proxy project
+pub struct GenerationReport {
+    /// The project as it was generated
+    pub project: ProxyProject,
+    /// Exports which were left out because the proxy can't provide them
+    pub left_out: Vec<UnprovidableExport>,
+    /// The files that were (or, for a dry run, would have been) created or changed
+    pub changes: Vec<FileChange>,
+    /// Exports that weren't in the project before
+    pub added: Vec<String>,
+    /// Exports that were in the project before, but no longer are
+    pub removed: Vec<String>,
+    /// Exports that had a generated thunk in `src/proxied_exports.rs`, which are now in `src/intercepted_exports.rs`
+    pub newly_intercepted: Vec<String>,
+    /// Exports the project had that the merged DLLs don't, eg. ones removed from a new version of the DLL.
+    /// They're kept, resolving to nothing, unless [`GenerationOptions::prune`] removed them
+    pub missing: Vec<String>,
+    /// Missing exports that are still hooked. Once pruned, their hooks no longer build
+    pub orphaned_hooks: Vec<String>,
+}
 
-    let proxy_gen = ProxyTemplates::new()?;
+impl GenerationReport {
+    /// Whether the project on disk already matched what was generated
+    pub fn is_up_to_date(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
 
-    if dll_name.contains('-') {
-        eprintln!(
-            "Detected hyphens in DLL name - the generated project will use underscores instead."
-        );
+/// The original names of every export the project provides, whether proxied or forwarded
+fn export_names(project: &ProxyProject) -> BTreeSet<String> {
+    project
+        .arches()
+        .iter()
+        .flat_map(|arch| arch.exports.iter().chain(arch.forwarded_exports.iter()))
+        .map(|export| export.original.clone())
+        .collect()
+}
+
+/// Original names of the exports that currently have a generated thunk in `src/proxied_exports.rs`
+fn read_proxied_exports(project_dir: &Path) -> Result<HashSet<String>> {
+    let mut proxied_exports = HashSet::new();
+    let proxied_re = Regex::new(r#"^#\[export_name\s*=\s*"(.+)"\]"#)?;
+    let proxied_path = project_dir.join("src").join("proxied_exports.rs");
+    if proxied_path.exists() {
+        for line in std::fs::read_to_string(proxied_path)?.lines() {
+            if let Some(captures) = proxied_re.captures(line.trim()) {
+                proxied_exports.insert(captures[1].to_string());
+            }
+        }
+    }
+    Ok(proxied_exports)
+}
+
+/// What to do when the folder for a new project already exists
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ScaffoldMode {
+    /// Fail if the folder exists
+    #[default]
+    New,
+    /// Write into the folder, as long as none of the project's files exist yet
+    IntoExisting,
+    /// Write into the folder, overwriting the files of an existing proxygen project.
+    /// Its hooks stay intercepted, and the files that are yours are kept: `src/intercepted_exports.rs`, `Cargo.toml`,
+    /// `.gitignore`, and `src/lib.rs` if it was changed by hand
+    Force,
+}
+
+/// The version control system to initialize a new project with
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Vcs {
+    #[default]
+    None,
+    Git,
+}
+
+impl std::str::FromStr for Vcs {
+    type Err = String;
+
+    fn from_str(vcs: &str) -> std::result::Result<Self, Self::Err> {
+        match vcs {
+            "none" => Ok(Vcs::None),
+            "git" => Ok(Vcs::Git),
+            _ => Err(format!("unknown vcs '{}', expected git or none", vcs)),
+        }
     }
+}
+
+/// How a new project gets written to disk
+#[derive(Clone, Debug, Default)]
+pub struct ScaffoldOptions {
+    pub mode: ScaffoldMode,
+    pub vcs: Vcs,
+    /// Where the new project loads the original DLL from
+    pub loading: LoadingStrategy,
+}
+
+/// Options shared by creating and updating a project
+pub struct GenerationOptions {
+    /// Exports that don't pass the filter are forwarded straight to the original DLL instead of being proxied
+    pub filter: ExportFilter,
+    /// Leave out exports the proxy can't provide, instead of failing with [`Error::UnprovidableExports`]
+    pub allow_unprovided: bool,
+    pub templates: ProxyTemplates,
+    /// Work out the changes without writing anything
+    pub dry_run: bool,
+    /// When merging, remove the exports the merged DLLs no longer have, instead of keeping them
+    pub prune: bool,
+}
+
+impl GenerationOptions {
+    /// No filter, the built in templates, and failing on unprovidable exports
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            filter: ExportFilter::default(),
+            allow_unprovided: false,
+            templates: ProxyTemplates::new()?,
+            dry_run: false,
+            prune: false,
+        })
+    }
+}
+
+/// Whether the folder already holds a project generated by proxygen, whose files we're allowed to overwrite
+fn is_proxygen_project(dir: &Path) -> bool {
+    dir.join(MANIFEST_FILE).is_file()
+        || dir.join("Cargo.toml").is_file()
+        && dir.join("src").join("orig_exports.rs").is_file()
+        && dir.join("src").join("export_indices.rs").is_file()
+}
+
+/// Whether `dir` is the root of a workspace with proxygen projects as members
+fn is_proxygen_workspace(dir: &Path) -> bool {
+    matches!(workspace_proxies(dir), Ok(Some(members)) if !members.is_empty())
+}
+
+/// Cargo package names may only contain letters, numbers, `-` and `_`, and can't start with a number
+fn is_valid_package_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
 
-    let src_cargo_toml = proxy_gen.get_cargo_toml(
+/// Works out which of the files can be written into `out_dir`, without overwriting anything we shouldn't
+/// `existing_project` is whether `out_dir` already holds a project of the kind being generated, and `user_files` are the
+/// files [`ScaffoldMode::Force`] keeps in it
+fn check_scaffold(
+    mut files: ProjectFiles,
+    out_dir: &Path,
+    mode: ScaffoldMode,
+    existing_project: bool,
+    user_files: &[PathBuf],
+) -> Result<ProjectFiles> {
+    if !out_dir.exists() {
+        return Ok(files);
+    }
+    if mode == ScaffoldMode::New {
+        return Err(Error::ProjectExists(out_dir.to_path_buf()));
+    }
+    if !out_dir.is_dir() {
+        return Err(Error::NotADirectory(out_dir.to_path_buf()));
+    }
+    if mode == ScaffoldMode::Force && existing_project {
+        for path in user_files {
+            if out_dir.join(path).exists() {
+                files.remove(path);
+            }
+        }
+        return Ok(files);
+    }
+    let existing: Vec<PathBuf> = files
+        .iter()
+        .map(|(path, _)| path.to_path_buf())
+        .filter(|path| out_dir.join(path).exists())
+        .collect();
+    if !existing.is_empty() {
+        return Err(Error::WouldOverwrite(existing));
+    }
+    Ok(files)
+}
+
+/// Creates a new proxy DLL rust project.
+///
+/// `originals` are the builds of the original DLL to proxy, at most one per arch. The first one is the arch the project
+/// builds for by default.
+/// The whole project is written in-process, so cargo doesn't need to be installed.
+pub fn create_proxy_project(
+    originals: &[ArchExports],
+    dll_name: impl Into<String>,
+    out_dir: &Path,
+    options: &GenerationOptions,
+    scaffold: ScaffoldOptions,
+) -> Result<GenerationReport> {
+    let package_name = match out_dir.canonicalize() {
+        Ok(out_dir) => out_dir.file_name().map(|name| name.to_string_lossy().to_string()),
+        Err(_) => out_dir.file_name().map(|name| name.to_string_lossy().to_string()),
+    }
+    .ok_or_else(|| Error::NotAProject(out_dir.to_path_buf()))?;
+    if !is_valid_package_name(&package_name) {
+        return Err(Error::InvalidPackageName(package_name));
+    }
+    let existing_project = is_proxygen_project(out_dir);
+    let force = scaffold.mode == ScaffoldMode::Force && existing_project;
+    let (project, left_out, lib) = new_project(
+        originals,
         package_name,
-        &dll_name.replace(".dll", "").replace('-', "_"),
-    )?;
-    let src_export_indices = proxy_gen.get_export_indices(exports)?;
-    let src_intercepted_exports = proxy_gen.get_intercepted_exports()?;
-    let src_lib = proxy_gen.get_lib(package_name)?;
-    let src_orig_exports = proxy_gen.get_orig_exports(exports)?;
-    let src_proxied_exports = proxy_gen.get_proxied_exports(exports, &HashSet::new())?;
-    // let src_module_def = proxy_gen.get_module_def(exports, &dll_name.replace('-', "_"))?;
-
-    std::fs::write(out_dir.join("Cargo.toml"), src_cargo_toml)?;
-    std::fs::write(
-        out_dir.join("rust-toolchain.toml"),
-        // "[toolchain]\nchannel = \"nightly-2023-11-05\"",
-        "[toolchain]\nchannel = \"nightly\"",
-    )?;
-    // std::fs::write(
-    //     out_dir.join("build.rs"),
-    //     "fn main() {\n    println!(\"cargo:rustc-link-arg=/DEF:module.def\");\n}",
-    // )?;
-    std::fs::write(
-        out_dir.join("src").join("export_indices.rs"),
-        src_export_indices,
+        dll_name,
+        options,
+        scaffold.loading,
+        |_| force.then(|| out_dir.to_path_buf()),
     )?;
-    std::fs::write(
-        out_dir.join("src").join("intercepted_exports.rs"),
-        src_intercepted_exports,
-    )?;
-    std::fs::write(out_dir.join("src").join("lib.rs"), src_lib)?;
-    std::fs::write(
-        out_dir.join("src").join("orig_exports.rs"),
-        src_orig_exports,
-    )?;
-    std::fs::write(
-        out_dir.join("src").join("proxied_exports.rs"),
-        src_proxied_exports,
-    )?;
-    // std::fs::write(out_dir.join("module.def"), src_module_def)?;
+    let mut files = generate_new_project(&project, lib, &options.templates)?;
+    if scaffold.vcs == Vcs::Git {
+        files.insert(".gitignore", "/target\n");
+    }
 
-    // Let's also set the default toolchain based on the arch
-    let target = match arch {
-        Arch::X86 => "i686-pc-windows-gnu",
-        Arch::X64 => "x86_64-pc-windows-msvc",
+    let files = check_scaffold(
+        files,
+        out_dir,
+        scaffold.mode,
+        existing_project,
+        &[
+            Path::new("src").join("intercepted_exports.rs"),
+            PathBuf::from("Cargo.toml"),
+            PathBuf::from(".gitignore"),
+        ],
+    )?;
+    let changes = files.changes(out_dir)?;
+    let added = export_names(&project).into_iter().collect();
+    let report = GenerationReport {
+        project,
+        left_out,
+        changes,
+        added,
+        removed: Vec::new(),
+        newly_intercepted: Vec::new(),
+        missing: Vec::new(),
+        orphaned_hooks: Vec::new(),
     };
+    if options.dry_run {
+        return Ok(report);
+    }
+    files.write_to(out_dir)?;
+    if scaffold.vcs == Vcs::Git {
+        git_init(out_dir)?;
+    }
+
+    Ok(report)
+}
+
+/// The name of the shared library crate in workspaces generated by [`create_proxy_workspace`]
+pub const HOOKS_CRATE: &str = "hooks";
+
+const HOOKS_CRATE_LIB: &str = "\
+// Code shared by every proxy DLL in the workspace. Each proxy depends on this crate as `hooks`, so hooks in its
+// src/intercepted_exports.rs can call into it.
+//
+// Every proxy DLL links its own copy of this crate, so statics in here aren't shared between the DLLs at runtime.
+";
+
+/// What [`create_proxy_workspace`] did
+pub struct WorkspaceReport {
+    /// The report of each member proxy, in the order their DLLs were given. Their changes' paths are relative to the
+    /// workspace root
+    pub members: Vec<GenerationReport>,
+    /// The files outside the member proxies that were (or, for a dry run, would have been) created or changed, eg. the
+    /// root `Cargo.toml` and the hooks crate
+    pub changes: Vec<FileChange>,
+}
 
-    // Set the default target based on the DLL's arch
-    println!(
-        "The generated project will use the {} target (can be changed in .cargo/config)",
-        target
+/// Creates a Cargo workspace in `out_dir`, with a proxy DLL project for each of `originals` and a [`HOOKS_CRATE`] library
+/// crate they all depend on, for code the proxies share.
+///
+/// `originals` holds each DLL's name and builds, as passed to [`create_proxy_project`]. Each member proxy is created in
+/// a folder named after its DLL, eg. `version` for `version.dll`, and the workspace root holds the toolchain and build
+/// target for all of them.
+pub fn create_proxy_workspace(
+    originals: &[(String, Vec<ArchExports>)],
+    out_dir: &Path,
+    options: &GenerationOptions,
+    scaffold: ScaffoldOptions,
+) -> Result<WorkspaceReport> {
+    let existing_workspace = is_proxygen_workspace(out_dir);
+    let force = scaffold.mode == ScaffoldMode::Force && existing_workspace;
+    let mut members: Vec<(String, ProxyProject, Vec<UnprovidableExport>)> = Vec::new();
+    let mut files = ProjectFiles::new();
+    for (dll_name, arches) in originals.iter() {
+        let (mut project, left_out, lib) = new_project(
+            arches,
+            "",
+            dll_name.as_str(),
+            options,
+            scaffold.loading.clone(),
+            |project| force.then(|| out_dir.join(project.lib_name())),
+        )?;
+        let member = project.lib_name();
+        if !is_valid_package_name(&member) {
+            return Err(Error::InvalidPackageName(member));
+        }
+        if member == HOOKS_CRATE || members.iter().any(|(other, _, _)| *other == member) {
+            return Err(Error::DuplicateWorkspaceMember(member));
+        }
+        project.package_name = member.clone();
+        project.hooks_crate = Some(HOOKS_CRATE.into());
+        for (path, contents) in generate_new_project(&project, lib, &options.templates)?.iter() {
+            files.insert(Path::new(&member).join(path), contents);
+        }
+        members.push((member, project, left_out));
+    }
+    let (_, first, _) = members.first().ok_or(Error::NoOriginals)?;
+
+    let member_names: Vec<String> = std::iter::once(HOOKS_CRATE)
+        .chain(members.iter().map(|(member, _, _)| member.as_str()))
+        .map(|member| format!("{:?}", member))
+        .collect();
+    files.insert(
+        "Cargo.toml",
+        format!(
+            "[workspace]\nmembers = [{}]\nresolver = \"2\"\n",
+            member_names.join(", ")
+        ),
+    );
+    files.insert("rust-toolchain.toml", RUST_TOOLCHAIN);
+    files.insert(Path::new(".cargo").join("config"), cargo_config(first.arch));
+    files.insert(
+        Path::new(HOOKS_CRATE).join("Cargo.toml"),
+        format!(
+            "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[dependencies]\n",
+            HOOKS_CRATE
+        ),
+    );
+    files.insert(
+        Path::new(HOOKS_CRATE).join("src").join("lib.rs"),
+        HOOKS_CRATE_LIB,
     );
-    if !out_dir.join(".cargo").exists() {
-        std::fs::create_dir(out_dir.join(".cargo"))?;
+    if scaffold.vcs == Vcs::Git {
+        files.insert(".gitignore", "/target\n");
     }
-    std::fs::write(
-        out_dir.join(".cargo").join("config"),
-        format!("[build]\ntarget = \"{}\"", target),
+
+    // The hooks crate is the user's own code, like each member's hooks and Cargo.toml
+    let mut user_files = vec![
+        PathBuf::from(".gitignore"),
+        Path::new(HOOKS_CRATE).join("Cargo.toml"),
+        Path::new(HOOKS_CRATE).join("src").join("lib.rs"),
+    ];
+    for (member, _, _) in members.iter() {
+        user_files.push(Path::new(member).join("src").join("intercepted_exports.rs"));
+        user_files.push(Path::new(member).join("Cargo.toml"));
+    }
+    let files = check_scaffold(
+        files,
+        out_dir,
+        scaffold.mode,
+        existing_workspace,
+        &user_files,
     )?;
+    let mut changes = files.changes(out_dir)?;
+    let mut reports = Vec::new();
+    for (member, project, left_out) in members {
+        let (member_changes, rest) = changes
+            .into_iter()
+            .partition(|change| change.path.starts_with(&member));
+        changes = rest;
+        reports.push(GenerationReport {
+            added: export_names(&project).into_iter().collect(),
+            project,
+            left_out,
+            changes: member_changes,
+            removed: Vec::new(),
+            newly_intercepted: Vec::new(),
+            missing: Vec::new(),
+            orphaned_hooks: Vec::new(),
+        });
+    }
+    let report = WorkspaceReport {
+        members: reports,
+        changes,
+    };
+    if options.dry_run {
+        return Ok(report);
+    }
+    files.write_to(out_dir)?;
+    if scaffold.vcs == Vcs::Git {
+        git_init(out_dir)?;
+    }
 
-    println!(
-        "Successfully created new DLL proxy project '{}' at {}",
-        package_name,
-        out_dir.to_string_lossy()
-    );
+    Ok(report)
+}
+
+/// The member proxies of the Cargo workspace at `dir`, or `None` if `dir` isn't the root of a workspace.
+///
+/// Members without a `proxygen.toml`, like a workspace's hooks crate, are skipped, as are glob members
+pub fn workspace_proxies(dir: &Path) -> Result<Option<Vec<PathBuf>>> {
+    let Ok(cargo_toml) = std::fs::read_to_string(dir.join("Cargo.toml")) else {
+        return Ok(None);
+    };
+    let cargo_toml: toml::Table = toml::from_str(&cargo_toml)
+        .map_err(|err| Error::InvalidWorkspace(dir.to_path_buf(), err.to_string()))?;
+    let Some(workspace) = cargo_toml.get("workspace") else {
+        return Ok(None);
+    };
+    let members = workspace
+        .get("members")
+        .and_then(|members| members.as_array())
+        .map(|members| members.as_slice())
+        .unwrap_or_default();
+    Ok(Some(
+        members
+            .iter()
+            .filter_map(|member| member.as_str())
+            .filter(|member| !member.contains('*'))
+            .map(|member| dir.join(member))
+            .filter(|member| member.join(MANIFEST_FILE).is_file())
+            .collect(),
+    ))
+}
+
+/// The member proxy of the Cargo workspace at `dir` that proxies `dll_name`
+pub fn find_workspace_proxy(dir: &Path, dll_name: &str) -> Result<PathBuf> {
+    for member in workspace_proxies(dir)?.unwrap_or_default() {
+        if ProxyProject::read(&member)?
+            .dll_name
+            .eq_ignore_ascii_case(dll_name)
+        {
+            return Ok(member);
+        }
+    }
+    Err(Error::NoWorkspaceProxy(dll_name.into(), dir.to_path_buf()))
+}
+
+/// Sets up a new project from the builds of its original DLL, returning the exports that had to be left out.
+///
+/// `existing` gives the folder of the project that [`ScaffoldMode::Force`] is regenerating, if there is one. Its hooks
+/// stay intercepted, so they don't also get forwarding thunks, and its `src/lib.rs` is regenerated with
+/// [`regenerate_lib`], which is returned for [`generate_new_project`]
+fn new_project(
+    originals: &[ArchExports],
+    package_name: impl Into<String>,
+    dll_name: impl Into<String>,
+    options: &GenerationOptions,
+    loading: LoadingStrategy,
+    existing: impl FnOnce(&ProxyProject) -> Option<PathBuf>,
+) -> Result<(
+    ProxyProject,
+    Vec<UnprovidableExport>,
+    Option<RegeneratedLib>,
+)> {
+    let (first, others) = originals.split_first().ok_or(Error::NoOriginals)?;
+    let mut project = ProxyProject::new(package_name, dll_name, first.arch, first.exports.clone());
+    project.original_sha256 = first.original_sha256.clone();
+    for original in others {
+        if project.arches().iter().any(|arch| arch.arch == original.arch) {
+            return Err(Error::DuplicateArch(format!("{:?}", original.arch)));
+        }
+        project.merge_arch_exports(original);
+    }
+    project.loading = loading;
+    let mut lib = None;
+    if let Some(existing) = existing(&project).filter(|dir| is_proxygen_project(dir)) {
+        project.intercepted_exports = find_intercepted_exports(&existing)?;
+        let regenerated = regenerate_lib(&existing, &project, &options.templates)?;
+        // A `src/lib.rs` that's kept may be from before `DllMain` could be forwarded
+        if let RegeneratedLib::Customized = regenerated {
+            project.forwards_dll_main =
+                std::fs::read_to_string(existing.join("src").join("lib.rs"))
+                    .is_ok_and(|lib| lib.contains("ORIG_DLL_MAIN_INDEX"));
+        }
+        lib = Some(regenerated);
+    }
+    project.apply_filter(&options.filter);
+    let left_out = project.exclude_unprovidable_exports(options.allow_unprovided)?;
+    Ok((project, left_out, lib))
+}
+
+/// Generates the files of a project set up by [`new_project`], with the `src/lib.rs` it regenerated, if any. A
+/// `src/lib.rs` that was changed by hand is left out, so it's kept
+fn generate_new_project(
+    project: &ProxyProject,
+    lib: Option<RegeneratedLib>,
+    templates: &ProxyTemplates,
+) -> Result<ProjectFiles> {
+    let mut files = project.generate(templates)?;
+    let lib_path = Path::new("src").join("lib.rs");
+    match lib {
+        Some(RegeneratedLib::Regenerated(lib, _)) => files.insert(lib_path, lib),
+        Some(RegeneratedLib::Customized) => {
+            files.remove(&lib_path);
+        }
+        None => {}
+    }
+    Ok(files)
+}
+
+/// Initializes a git repository in `out_dir`, unless there already is one
+fn git_init(out_dir: &Path) -> Result<()> {
+    if out_dir.join(".git").exists() {
+        return Ok(());
+    }
+    let status = Command::new("git")
+        .arg("init")
+        .arg("--quiet")
+        .arg(out_dir)
+        .status()?;
+    if !status.success() {
+        return Err(Error::GitInit {
+            path: out_dir.to_path_buf(),
+            code: status.code(),
+        });
+    }
     Ok(())
 }
 
-/// Updates an existing proxy DLL rust project
-pub fn update_proxy_project(exports: &BTreeSet<ExportName>, out_dir: &PathBuf) -> Result<()> {
-    // TODO: Update module.def in here too
-    if !out_dir.exists() {
-        return Err(anyhow::anyhow!(
-            "Folder {} doesn't exist. Consider creating a new proxy project instead. Aborting",
-            out_dir.to_string_lossy()
-        ));
+/// Updates an existing proxy DLL rust project, merging in any new exports.
+///
+/// `originals` are the builds of the original DLL whose exports get merged in, at most one per arch, along with their
+/// hashes, which get recorded in the manifest. A build for an arch the project doesn't proxy yet adds that arch.
+/// A non-empty filter re-decides which of the project's exports are proxied and which are forwarded.
+/// Exports a merged build no longer has are reported as missing, and removed if [`GenerationOptions::prune`] is set.
+/// For a dry run, nothing is written, but the report still lists every change that would have been made.
+pub fn update_proxy_project(
+    originals: &[ArchExports],
+    out_dir: &Path,
+    options: &GenerationOptions,
+) -> Result<GenerationReport> {
+    let mut project = ProxyProject::read(out_dir)?;
+    let old_exports = export_names(&project);
+    let old_proxied = read_proxied_exports(out_dir)?;
+    let mut missing = BTreeSet::new();
+    for (i, original) in originals.iter().enumerate() {
+        if originals[..i].iter().any(|other| other.arch == original.arch) {
+            return Err(Error::DuplicateArch(format!("{:?}", original.arch)));
+        }
+        let arch_missing = match options.prune {
+            true => project.prune_exports(original),
+            false => project.missing_exports(original),
+        };
+        missing.extend(arch_missing.into_iter().map(|export| export.original));
+        project.merge_arch_exports(original);
     }
-    if !out_dir.join("Cargo.toml").exists() {
-        return Err(anyhow::anyhow!(
-            "Directory '{}' doesn't seem to contain a Cargo.toml file. Aborting",
-            out_dir
-                .canonicalize()
-                .as_deref()
-                .unwrap_or(out_dir)
-                .to_string_lossy()
-        ));
+    let orphaned_hooks = missing
+        .iter()
+        .filter(|export| project.intercepted_exports.contains(*export))
+        .cloned()
+        .collect();
+    project.apply_filter(&options.filter);
+    let left_out = project.exclude_unprovidable_exports(options.allow_unprovided)?;
+    let files = project.generate_exports(&options.templates)?;
+
+    let new_exports = export_names(&project);
+    let mut newly_intercepted: Vec<String> = project
+        .intercepted_exports
+        .intersection(&old_proxied)
+        .cloned()
+        .collect();
+    newly_intercepted.sort();
+    let report = GenerationReport {
+        changes: files.changes(out_dir)?,
+        added: new_exports.difference(&old_exports).cloned().collect(),
+        removed: old_exports.difference(&new_exports).cloned().collect(),
+        newly_intercepted,
+        missing: missing.into_iter().collect(),
+        orphaned_hooks,
+        project,
+        left_out,
+    };
+    if !options.dry_run {
+        files.write_to(out_dir)?;
     }
 
-    let package_name = out_dir
-        .file_name()
-        .expect("Invalid output directory name")
-        .to_str()
-        .expect("Unable to detect package name");
+    Ok(report)
+}
 
-    // Existing intercepted exports
-    let mut intercepted_exports: HashSet<String> = HashSet::new();
-    // All exports, ie. existing ones + new ones
-    let mut all_exports: BTreeSet<ExportName> = BTreeSet::new();
+/// Dependencies that older versions of proxygen generated projects with, which the built in templates no longer use
+const RETIRED_DEPENDENCIES: &[&str] = &["winapi"];
 
-    // Get intercepted exports from src/intercepted_exports.rs
-    let exports_re = Regex::new(r"^pub.*extern.*fn\s+(.+)\(")?;
-    if out_dir.join("src").join("intercepted_exports.rs").exists() {
-        for line in
-            std::fs::read_to_string(out_dir.join("src").join("intercepted_exports.rs"))?.lines()
-        {
-            let captures = exports_re.captures(line.trim());
-            if let Some(captures) = captures {
-                let export_name = captures.get(1).unwrap().as_str().trim();
-                intercepted_exports.insert(export_name.into());
-                println!("Detected intercepted export: {}", export_name);
-            }
+/// What [`upgrade_proxy_project`] did
+pub struct UpgradeReport {
+    /// The project as it was regenerated
+    pub project: ProxyProject,
+    /// The files that were (or, for a dry run, would have been) changed
+    pub changes: Vec<FileChange>,
+    /// Dependencies that were removed from the project's `Cargo.toml`, eg. `winapi`. Hooks that still use them need
+    /// them added back
+    pub removed_dependencies: Vec<String>,
+    /// Modules the user declared in `src/lib.rs`, eg. `hooks`, which the upgraded one still declares
+    pub kept_modules: Vec<String>,
+    /// Files that were changed by hand, so they were left as they were instead of being upgraded
+    pub not_upgraded: Vec<PathBuf>,
+}
+
+/// Upgrades a project generated by an older version of proxygen to this version's templates.
+///
+/// Every file [`update_proxy_project`] regenerates is rendered again, and so is `src/lib.rs` as long as it wasn't
+/// changed by hand (see [`regenerate_lib`]). The dependencies of the generated `Cargo.toml` are merged into the
+/// project's own: ones it's missing are added, `proxygen-macros` is pinned to this version, and
+/// [`RETIRED_DEPENDENCIES`] the templates no longer use are removed. Everything else in `Cargo.toml`, and the hooks, are
+/// left alone. An existing `rust-toolchain.toml` (the workspace root's, for a workspace member) is moved off the
+/// nightly toolchain older versions pinned.
+pub fn upgrade_proxy_project(out_dir: &Path, options: &GenerationOptions) -> Result<UpgradeReport> {
+    let mut project = ProxyProject::read(out_dir)?;
+    project.macros_version = Some(env!("CARGO_PKG_VERSION").into());
+    let mut files = project.generate_exports(&options.templates)?;
+    let mut kept_modules = Vec::new();
+    let mut not_upgraded = Vec::new();
+    let lib_path = Path::new("src").join("lib.rs");
+    match regenerate_lib(out_dir, &project, &options.templates)? {
+        RegeneratedLib::Regenerated(lib, modules) => {
+            files.insert(&lib_path, lib);
+            kept_modules = modules;
         }
+        RegeneratedLib::Customized => not_upgraded.push(lib_path),
+    }
+    let cargo_toml_path = out_dir.join("Cargo.toml");
+    let (cargo_toml, removed_dependencies) = upgrade_cargo_toml(
+        &std::fs::read_to_string(&cargo_toml_path)?,
+        &options.templates.get_cargo_toml(
+            &project.package_name,
+            project.lib_name(),
+            project.hooks_crate.as_deref(),
+        )?,
+        !not_upgraded.is_empty(),
+    )
+    .map_err(|err| Error::InvalidCargoToml(cargo_toml_path, err))?;
+    files.insert("Cargo.toml", cargo_toml);
+    // Workspace members share the toolchain file of the workspace root. An older `src/lib.rs` that wasn't upgraded
+    // may still need nightly
+    let rust_toolchain = match project.hooks_crate {
+        Some(_) => Path::new("..").join("rust-toolchain.toml"),
+        None => PathBuf::from("rust-toolchain.toml"),
+    };
+    if out_dir.join(&rust_toolchain).is_file() && not_upgraded.is_empty() {
+        files.insert(rust_toolchain, RUST_TOOLCHAIN);
     }
 
-    // Get existing exports from src/orig_exports
-    let exports_index_re =
-        Regex::new(r#"^load_dll_func\(Index_(.+)\s*,\s*dll_handle,\s*"(.+)"\)\s*;"#)?;
-    if out_dir.join("src").join("orig_exports.rs").exists() {
-        for line in std::fs::read_to_string(out_dir.join("src").join("orig_exports.rs"))?.lines() {
-            let captures = exports_index_re.captures(line.trim());
-            if let Some(captures) = captures {
-                let cleaned = String::from(captures.get(1).unwrap().as_str().trim());
-                let original = String::from(captures.get(2).unwrap().as_str());
-                all_exports.insert(ExportName { original, cleaned });
+    let report = UpgradeReport {
+        changes: files.changes(out_dir)?,
+        project,
+        removed_dependencies,
+        kept_modules,
+        not_upgraded,
+    };
+    if !options.dry_run {
+        files.write_to(out_dir)?;
+    }
+
+    Ok(report)
+}
+
+/// A project's `src/lib.rs`, as regenerated by [`regenerate_lib`]
+enum RegeneratedLib {
+    /// The new `src/lib.rs`, and the modules the user declared in the old one, which it keeps declaring
+    Regenerated(String, Vec<String>),
+    /// The old `src/lib.rs` was changed by hand, besides declaring modules, so it can't be regenerated without losing
+    /// those changes
+    Customized,
+}
+
+/// Renders `src/lib.rs` again for the project at `project_dir`, without losing anything the user added to it.
+///
+/// The existing file is only replaced if it still matches one of the versions proxygen generates (see
+/// [`ProxyTemplates::get_known_libs`]), ignoring formatting and comments. Modules the user declared in it, eg. `mod hooks;`
+/// for hooks in `src/hooks/`, don't count as changes, and are declared in the new file too
+fn regenerate_lib(
+    project_dir: &Path,
+    project: &ProxyProject,
+    templates: &ProxyTemplates,
+) -> Result<RegeneratedLib> {
+    let lib = templates.get_lib(&project.package_name, &project.dll_name, &project.loading)?;
+    let Ok(existing) = std::fs::read_to_string(project_dir.join("src").join("lib.rs")) else {
+        return Ok(RegeneratedLib::Regenerated(lib, Vec::new()));
+    };
+    let (Ok(mut existing_file), Ok(lib_file)) = (syn::parse_file(&existing), syn::parse_file(&lib))
+    else {
+        return Ok(RegeneratedLib::Customized);
+    };
+
+    // Modules the templates don't declare are the user's
+    let generated_modules: HashSet<String> = lib_file
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            syn::Item::Mod(module) => Some(module.ident.to_string()),
+            _ => None,
+        })
+        .collect();
+    let mut user_modules = Vec::new();
+    existing_file.items.retain(|item| match item {
+        syn::Item::Mod(module) if !generated_modules.contains(&module.ident.to_string()) => {
+            user_modules.push(module.clone());
+            false
+        }
+        _ => true,
+    });
+    let existing_tokens = existing_file.to_token_stream().to_string();
+    let known = templates
+        .get_known_libs(&project.package_name, &project.dll_name, &project.loading)?
+        .into_iter()
+        .any(|known| {
+            syn::parse_file(&known)
+                .is_ok_and(|known| known.to_token_stream().to_string() == existing_tokens)
+        });
+    if !known {
+        return Ok(RegeneratedLib::Customized);
+    }
+
+    // Declare the user's modules right after the generated ones, copied over as they were written
+    let existing_lines: Vec<&str> = existing.lines().collect();
+    let declarations: Vec<&str> = user_modules
+        .iter()
+        .flat_map(|module| {
+            let span = module.span();
+            // Along with any comments right above the declaration
+            let mut start = span.start().line - 1;
+            while start > 0 && existing_lines[start - 1].trim_start().starts_with("//") {
+                start -= 1;
             }
+            existing_lines[start..span.end().line].iter().copied()
+        })
+        .collect();
+    let insert_at = lib_file
+        .items
+        .iter()
+        .filter(|item| matches!(item, syn::Item::Mod(_)))
+        .map(|item| item.span().end().line)
+        .max()
+        .unwrap_or(0);
+    let mut lines: Vec<&str> = lib.lines().collect();
+    lines.splice(insert_at..insert_at, declarations);
+    let mut regenerated = lines.join("\n");
+    if lib.ends_with('\n') {
+        regenerated.push('\n');
+    }
+    Ok(RegeneratedLib::Regenerated(
+        regenerated,
+        user_modules
+            .iter()
+            .map(|module| module.ident.to_string())
+            .collect(),
+    ))
+}
+
+/// Merges the dependencies of the `generated` `Cargo.toml` into the project's `cargo_toml`, returning it along with the
+/// retired dependencies that were removed. Those are kept if `keep_retired` is set, eg. for a `src/lib.rs` that wasn't
+/// upgraded and still uses them
+fn upgrade_cargo_toml(
+    cargo_toml: &str,
+    generated: &str,
+    keep_retired: bool,
+) -> std::result::Result<(String, Vec<String>), String> {
+    let mut cargo_toml: toml_edit::DocumentMut =
+        cargo_toml.parse().map_err(|err| format!("{}", err))?;
+    let generated: toml_edit::DocumentMut = generated
+        .parse()
+        .map_err(|err| format!("the generated Cargo.toml is invalid: {}", err))?;
+    let Some(generated) = generated
+        .get("dependencies")
+        .and_then(|dependencies| dependencies.as_table_like())
+    else {
+        return Ok((cargo_toml.to_string(), Vec::new()));
+    };
+    let dependencies = cargo_toml
+        .entry("dependencies")
+        .or_insert_with(toml_edit::table)
+        .as_table_like_mut()
+        .ok_or("[dependencies] isn't a table")?;
+
+    let mut removed = Vec::new();
+    for retired in RETIRED_DEPENDENCIES.iter().filter(|_| !keep_retired) {
+        if !generated.contains_key(retired) && dependencies.remove(retired).is_some() {
+            removed.push(retired.to_string());
         }
     }
+    for (name, dependency) in generated.iter() {
+        if name == "proxygen-macros" || !dependencies.contains_key(name) {
+            dependencies.insert(name, dependency.clone());
+        }
+    }
+    Ok((cargo_toml.to_string(), removed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
 
-    // Add on the new exports that aren't already in all exports
-    for export in exports.iter() {
-        all_exports.insert(export.clone());
+    #[test]
+    fn refuses_template_packs_for_other_versions() {
+        let pack = TempDir::new("refuses_template_packs_for_other_versions");
+        ProxyTemplates::export_builtin()
+            .unwrap()
+            .write_to(pack.path())
+            .unwrap();
+        assert!(ProxyTemplates::from_dir(pack.path()).is_ok());
+
+        // Packs exported before the templates built on stable still render a lib.rs that needs nightly
+        pack.write(TEMPLATE_PACK_MANIFEST, "version = 7\n");
+        assert!(matches!(
+            ProxyTemplates::from_dir(pack.path()),
+            Err(Error::IncompatibleTemplatePack { version: 7, .. })
+        ));
     }
 
-    let proxy_gen = ProxyTemplates::new()?;
+    #[test]
+    fn regenerates_lib_with_user_modules() {
+        let dir = TempDir::new("regenerates_lib_with_user_modules");
+        let templates = ProxyTemplates::new().unwrap();
+        let project = ProxyProject::new("proxy", "foo.dll", Arch::X64, BTreeSet::new());
+        let lib = templates
+            .get_lib(&project.package_name, &project.dll_name, &project.loading)
+            .unwrap();
+        let regenerate = || regenerate_lib(dir.path(), &project, &templates).unwrap();
+        assert!(matches!(
+            regenerate(),
+            RegeneratedLib::Regenerated(new, modules) if new == lib && modules.is_empty()
+        ));
 
-    let src_export_indices = proxy_gen.get_export_indices(&all_exports)?;
-    let src_orig_exports = proxy_gen.get_orig_exports(&all_exports)?;
-    let src_proxied_exports = proxy_gen.get_proxied_exports(&all_exports, &intercepted_exports)?;
+        // A lib.rs generated by 0.5.1, with the user's hooks declared in it
+        let legacy = templates
+            .get_known_libs(&project.package_name, &project.dll_name, &project.loading)
+            .unwrap()
+            .pop()
+            .unwrap();
+        assert_ne!(legacy, lib);
+        dir.write("src/lib.rs", &format!("{}\n// Hooks\nmod hooks;\n", legacy));
+        let RegeneratedLib::Regenerated(new, modules) = regenerate() else {
+            panic!("lib.rs should be regenerated");
+        };
+        assert_eq!(modules, ["hooks"]);
+        assert!(new.contains("// Hooks\nmod hooks;\n"));
+        assert_eq!(new.replace("// Hooks\nmod hooks;\n", ""), lib);
 
-    std::fs::write(
-        out_dir.join("src").join("export_indices.rs"),
-        src_export_indices,
-    )?;
-    std::fs::write(
-        out_dir.join("src").join("orig_exports.rs"),
-        src_orig_exports,
-    )?;
-    std::fs::write(
-        out_dir.join("src").join("proxied_exports.rs"),
-        src_proxied_exports,
-    )?;
+        // Anything else means the file was changed by hand
+        dir.write("src/lib.rs", &format!("{}\nfn extra() {{}}\n", legacy));
+        assert!(matches!(regenerate(), RegeneratedLib::Customized));
+        dir.write("src/lib.rs", "not rust");
+        assert!(matches!(regenerate(), RegeneratedLib::Customized));
+    }
 
-    println!(
-        "Successfully updated DLL proxy project '{}' at {}",
-        package_name,
-        out_dir.to_string_lossy()
-    );
-    Ok(())
+    #[test]
+    fn upgrades_cargo_toml_dependencies() {
+        let cargo_toml = "[package]\nname = \"proxy\" # renamed by hand\n\n[dependencies]\nproxygen-macros = \"=0.5.1\"\nwinapi = \"0.3\"\nserde = \"1\"\n";
+        let generated = "[package]\nname = \"other\"\n\n[dependencies]\nproxygen-macros = \"=0.6.0\"\nwindows-sys = \"0.59\"\n";
+        let (upgraded, removed) = upgrade_cargo_toml(cargo_toml, generated, false).unwrap();
+        assert_eq!(removed, ["winapi"]);
+        assert_eq!(
+            upgraded,
+            "[package]\nname = \"proxy\" # renamed by hand\n\n[dependencies]\nproxygen-macros = \"=0.6.0\"\nserde = \"1\"\nwindows-sys = \"0.59\"\n"
+        );
+
+        // An old lib.rs that wasn't upgraded still needs winapi
+        let (upgraded, removed) = upgrade_cargo_toml(cargo_toml, generated, true).unwrap();
+        assert!(removed.is_empty());
+        assert!(upgraded.contains("winapi = \"0.3\""));
+
+        assert!(upgrade_cargo_toml("[dependencies", generated, false).is_err());
+        assert!(upgrade_cargo_toml("dependencies = 1\n", generated, false).is_err());
+    }
+
+    /// The build script template is plain Rust, so its `module.def` parser can be tested as is
+    mod build_template {
+        #![allow(dead_code)]
+        include!("templates/build");
+
+        #[test]
+        fn reads_module_def_lines() {
+            assert_eq!(to_msvc_export(""), None);
+            assert_eq!(
+                to_msvc_export(r#"    "?foo@@YAXXZ" @1"#),
+                Some(("?foo@@YAXXZ".into(), "?foo@@YAXXZ,@1".into()))
+            );
+            assert_eq!(
+                to_msvc_export(r#"    "Counter" = "foo.dll_.Counter" @2 DATA"#),
+                Some(("Counter".into(), "Counter=foo.dll_.Counter,@2,DATA".into()))
+            );
+            assert_eq!(
+                to_msvc_export(r#"    "Ordinal4" = "foo.dll_.#4" @4 NONAME"#),
+                Some(("Ordinal4".into(), "Ordinal4=foo.dll_.#4,@4,NONAME".into()))
+            );
+            assert_eq!(
+                to_msvc_export(r#"    "DllRegisterServer" PRIVATE"#),
+                Some((
+                    "DllRegisterServer".into(),
+                    "DllRegisterServer,PRIVATE".into()
+                ))
+            );
+        }
+    }
+
+    fn export(name: &str, ordinal: u16) -> ExportName {
+        ExportName {
+            ordinal: Some(ordinal),
+            ..ExportName::new(name)
+        }
+    }
+
+    #[test]
+    fn writes_module_def_lines() {
+        assert_eq!(
+            module_def_line(&export("?foo@@YAXXZ", 1), None).unwrap(),
+            r#""?foo@@YAXXZ" @1"#
+        );
+        let counter = ExportName {
+            kind: ExportKind::Data,
+            ..export("Counter", 2)
+        };
+        assert_eq!(
+            module_def_line(&counter, Some("foo.dll_")).unwrap(),
+            r#""Counter" = "foo.dll_.Counter" @2 DATA"#
+        );
+        let unnamed = ExportName {
+            noname: true,
+            ..export("Ordinal4", 4)
+        };
+        assert_eq!(
+            module_def_line(&unnamed, Some("foo.dll_")).unwrap(),
+            r#""Ordinal4" = "foo.dll_.#4" @4 NONAME"#
+        );
+        assert_eq!(
+            module_def_line(&ExportName::new("DllRegisterServer"), None).unwrap(),
+            r#""DllRegisterServer" PRIVATE"#
+        );
+        assert!(matches!(
+            module_def_line(&ExportName::new("Foo\"Bar"), None),
+            Err(Error::UnprovidableExports(_))
+        ));
+    }
+
+    #[test]
+    fn quotes_original_export_names() {
+        let exports = BTreeSet::from([ExportName::new(r"Foo\Bar")]);
+        let orig_exports = ProxyTemplates::new()
+            .unwrap()
+            .get_orig_exports(&exports)
+            .unwrap();
+        assert!(orig_exports.contains(r#"dll_handle, "Foo\\Bar");"#));
+    }
 }
@@ -0,0 +1,251 @@
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::{
+    exports::{DLLFile, ExportKind, RESERVED_SYMBOL_PREFIX},
+    Error, Result,
+};
+
+/// File extensions that get picked up when scanning a directory
+const DLL_EXTENSIONS: &[&str] = &["dll"];
+
+/// A summary of a single DLL found while scanning a directory
+#[derive(Serialize)]
+pub struct DLLSummary {
+    pub path: String,
+    pub arch: Option<String>,
+    pub exports: usize,
+    pub forwarders: usize,
+    pub data_exports: usize,
+    /// Whether the DLL exports `DllGetClassObject`, ie. it is an in-process COM server
+    pub com_server: bool,
+    /// Whether the DLL carries an Authenticode signature (which isn't verified)
+    pub signed: bool,
+    /// Whether the DLL looks like a proxy generated by proxygen
+    pub proxygen_proxy: bool,
+    /// Set if the DLL couldn't be read, in which case nothing else is filled in
+    pub error: Option<String>,
+}
+
+impl DLLSummary {
+    fn new(dll: &Path, root: &Path) -> Self {
+        match Self::summarize(dll) {
+            Ok(summary) => Self {
+                path: relative_path(dll, root),
+                ..summary
+            },
+            Err(err) => Self::failed(dll, root, err),
+        }
+    }
+
+    /// The row for a DLL, or a folder under the scanned one, that couldn't be read
+    fn failed(path: &Path, root: &Path, err: impl std::fmt::Display) -> Self {
+        Self {
+            path: relative_path(path, root),
+            arch: None,
+            exports: 0,
+            forwarders: 0,
+            data_exports: 0,
+            com_server: false,
+            signed: false,
+            proxygen_proxy: false,
+            error: Some(err.to_string()),
+        }
+    }
+
+    fn summarize(dll: &Path) -> Result<Self> {
+        let dll_file = DLLFile::new(dll)?;
+        let exports = dll_file.parse_exports()?;
+        let has_export = |name: &str| exports.iter().any(|export| export.original == name);
+        Ok(Self {
+            path: String::new(),
+            arch: Some(dll_file.get_arch_name()?),
+            exports: exports.len(),
+            forwarders: exports
+                .iter()
+                .filter(|export| export.forwarder.is_some())
+                .count(),
+            data_exports: exports
+                .iter()
+                .filter(|export| export.kind == ExportKind::Data)
+                .count(),
+            com_server: has_export("DllGetClassObject"),
+            signed: dll_file.is_signed()?,
+            // Older proxies exported their internals without the reserved prefix
+            proxygen_proxy: exports
+                .iter()
+                .any(|export| export.original.starts_with(RESERVED_SYMBOL_PREFIX))
+                || (has_export("ORIG_FUNCS_PTR") && has_export("wait_dll_proxy_init")),
+            error: None,
+        })
+    }
+}
+
+fn relative_path(path: &Path, root: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Recursively collects every DLL under the given directory, along with the folders under it that couldn't be read.
+///
+/// Symlinked folders aren't followed, so a link back up the tree can't send this round in circles.
+fn find_dlls(dir: &Path, dlls: &mut Vec<PathBuf>, unreadable: &mut Vec<(PathBuf, std::io::Error)>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => return unreadable.push((dir.to_path_buf(), err)),
+    };
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                unreadable.push((dir.to_path_buf(), err));
+                continue;
+            }
+        };
+        let path = entry.path();
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => find_dlls(&path, dlls, unreadable),
+            Ok(_) => {
+                if path
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| DLL_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+                {
+                    dlls.push(path);
+                }
+            }
+            Err(err) => unreadable.push((path, err)),
+        }
+    }
+}
+
+/// Summarizes every DLL under the given directory, sorted by path.
+///
+/// DLLs, and folders, that can't be read get a summary with just the error filled in.
+pub fn scan_directory(dir: &Path) -> Result<Vec<DLLSummary>> {
+    if !dir.is_dir() {
+        return Err(Error::NotADirectory(dir.to_path_buf()));
+    }
+    let mut dlls = Vec::new();
+    let mut unreadable = Vec::new();
+    find_dlls(dir, &mut dlls, &mut unreadable);
+    let mut summaries: Vec<DLLSummary> = dlls
+        .iter()
+        .map(|dll| DLLSummary::new(dll, dir))
+        .chain(
+            unreadable
+                .iter()
+                .map(|(path, err)| DLLSummary::failed(path, dir, err)),
+        )
+        .collect();
+    summaries.sort_by(|a, b| Path::new(&a.path).cmp(Path::new(&b.path)));
+    Ok(summaries)
+}
+
+/// Prints the summaries as a plain text table
+pub fn print_table(summaries: &[DLLSummary]) {
+    let yes_no = |value: bool| if value { "yes" } else { "no" };
+    let header = [
+        "PATH", "ARCH", "EXPORTS", "FORWARDERS", "DATA", "COM", "SIGNED", "PROXYGEN",
+    ];
+    let rows: Vec<[String; 8]> = summaries
+        .iter()
+        .map(|summary| match &summary.error {
+            Some(_) => [
+                summary.path.clone(),
+                "?".into(),
+                "-".into(),
+                "-".into(),
+                "-".into(),
+                "-".into(),
+                "-".into(),
+                "-".into(),
+            ],
+            None => [
+                summary.path.clone(),
+                summary.arch.clone().unwrap_or_default(),
+                summary.exports.to_string(),
+                summary.forwarders.to_string(),
+                summary.data_exports.to_string(),
+                yes_no(summary.com_server).into(),
+                yes_no(summary.signed).into(),
+                yes_no(summary.proxygen_proxy).into(),
+            ],
+        })
+        .collect();
+
+    let mut widths = header.map(|column| column.len());
+    for row in rows.iter() {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    let print_row = |cells: Vec<&str>| {
+        let line: Vec<String> = cells
+            .iter()
+            .zip(widths.iter())
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    };
+    print_row(header.to_vec());
+    for row in rows.iter() {
+        print_row(row.iter().map(String::as_str).collect());
+    }
+
+    for summary in summaries.iter() {
+        if let Some(err) = &summary.error {
+            eprintln!("Failed to read {}: {}", summary.path, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture_path, TempDir};
+
+    #[test]
+    fn scans_dlls_in_nested_folders() {
+        let dir = TempDir::new("scans_dlls_in_nested_folders");
+        std::fs::create_dir_all(dir.path().join("sub")).unwrap();
+        std::fs::copy(
+            fixture_path("fixture.dll"),
+            dir.path().join("sub").join("Fixture.DLL"),
+        )
+        .unwrap();
+        dir.write("broken.dll", "not a DLL");
+        dir.write("readme.txt", "not a DLL either");
+        // A link back up the tree isn't followed
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("sub").join("loop")).unwrap();
+
+        let summaries = scan_directory(dir.path()).unwrap();
+        let paths: Vec<&str> = summaries
+            .iter()
+            .map(|summary| summary.path.as_str())
+            .collect();
+        let fixture = Path::new("sub").join("Fixture.DLL");
+        assert_eq!(paths, ["broken.dll", fixture.to_str().unwrap()]);
+
+        assert!(summaries[0].error.is_some());
+        let fixture = &summaries[1];
+        assert_eq!(fixture.error, None);
+        assert_eq!(fixture.exports, 5);
+        assert_eq!(fixture.forwarders, 1);
+        assert_eq!(fixture.data_exports, 1);
+        assert!(!fixture.com_server);
+        assert!(!fixture.signed);
+        assert!(!fixture.proxygen_proxy);
+    }
+
+    #[test]
+    fn refuses_files() {
+        assert!(matches!(
+            scan_directory(&fixture_path("fixture.dll")),
+            Err(Error::NotADirectory(_))
+        ));
+    }
+}
@@ -0,0 +1,48 @@
+//! Helpers shared by the unit tests
+
+use std::path::{Path, PathBuf};
+
+/// The path of a file in `tests/fixtures`
+pub fn fixture_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(name)
+}
+
+/// The contents of a file in `tests/fixtures`
+pub fn fixture(name: &str) -> Vec<u8> {
+    std::fs::read(fixture_path(name)).expect("fixture should exist")
+}
+
+/// A folder under the system temp folder that's removed again when dropped
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Creates an empty folder, named after the test using it so tests running in parallel don't share one
+    pub fn new(name: &str) -> Self {
+        let dir =
+            std::env::temp_dir().join(format!("proxygen-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("temp folder should be creatable");
+        Self(dir)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Writes `contents` to the file at `path`, relative to the folder, creating any missing parent folders
+    pub fn write(&self, path: impl AsRef<Path>, contents: &str) {
+        let path = self.0.join(path);
+        std::fs::create_dir_all(path.parent().expect("files have a parent folder"))
+            .expect("parent folder should be creatable");
+        std::fs::write(path, contents).expect("file should be writable");
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
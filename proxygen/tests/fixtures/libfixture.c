@@ -0,0 +1,9 @@
+int counter = 1;
+__attribute__((weak)) int weak_fn(void) { return 0; }
+__attribute__((visibility("protected"))) int protected_fn(void) { return 0; }
+__attribute__((visibility("hidden"))) int hidden_fn(void) { return 0; }
+int versioned_v1(void) { return 1; }
+int versioned_v2(void) { return 2; }
+__asm__(".symver versioned_v1, versioned@VERS_1");
+__asm__(".symver versioned_v2, versioned@@VERS_2");
+__asm__(".globl unique_obj\n.type unique_obj, @gnu_unique_object\n.section .data\nunique_obj: .long 0\n.size unique_obj, 4\n.text");
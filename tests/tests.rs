@@ -1,11 +1,51 @@
 // These "tests" are literally just here for me to experiment with proc macros at the moment
 // in the future there might end up being some actual tests here though
 
-use proxygen_macros::{proxy, pre_hook, post_hook};
+#![allow(non_snake_case, dead_code)]
 
+use proxygen_macros::{forward, post_hook, pre_hook, proxy};
+
+// Stand-ins for what the generated `lib.rs` and `src/export_indices.rs` provide to the macros
+mod export_indices {
+    pub const fn index_of(_export_name: &str) -> usize {
+        0
+    }
+}
+
+static mut ORIGINAL_FUNCS: [Option<unsafe extern "system" fn() -> isize>; 1] = [None];
+static mut ORIG_FUNCS_PTR: *const Option<unsafe extern "system" fn() -> isize> = std::ptr::null();
+
+pub extern "C" fn wait_dll_proxy_init() {}
+
+#[forward]
+#[no_mangle]
+pub extern "C" fn SomeForwardedFunction() {}
 
 #[pre_hook(sig = "unknown")]
 #[no_mangle]
 pub fn SomeTestFunction() {
     println!("This is a test!");
-}
\ No newline at end of file
+}
+
+// A hook with the same function name in another module, intercepting a different export
+mod other_hooks {
+    use proxygen_macros::pre_hook;
+
+    #[pre_hook(sig = "unknown")]
+    #[export_name = "SomeOtherTestFunction"]
+    pub fn SomeTestFunction() {
+        println!("This is another test!");
+    }
+}
+
+#[proxy(sig = "known")]
+#[export_name = "SomeProxiedFunction"]
+pub extern "C" fn SomeProxiedFunction(some_arg: u32) -> u32 {
+    orig_func(some_arg)
+}
+
+#[post_hook(sig = "known")]
+#[export_name = "SomePostHookedFunction"]
+pub extern "C" fn SomePostHookedFunction(some_arg: u32) -> u32 {
+    println!("Post-hooked with {}, returning {}", some_arg, orig_result);
+}